@@ -1 +1 @@
-battlesnake play --url http://localhost:8000 --url http://localhost:8000 --url http://localhost:8001 --url http://localhost:8001 --browser
\ No newline at end of file
+battlesnake play --url http://localhost:8000/team/a --url http://localhost:8000/team/a --url http://localhost:8001/team/b --url http://localhost:8001/team/b --browser
\ No newline at end of file
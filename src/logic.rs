@@ -10,82 +10,228 @@
 // To get you started we've included code to prevent your Battlesnake from moving backwards.
 // For more info see docs.battlesnake.com
 
+pub mod engine;
+mod mcts;
 mod mm_search;
+// Full rules engine, the search itself runs on the faster SimpleBoard
+mod rules;
 pub mod simple;
+pub mod snakes;
+pub mod teams;
+pub mod time;
+mod transposition;
+pub mod weights;
+mod zobrist;
 
-use log::info;
+use log::{debug, info, warn};
 //use rand::seq::SliceRandom;
 use serde_json::{json, Value};
+use std::any::Any;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
 
-use crate::{Battlesnake, Board, Coord, Game, GameInfo};
+use crate::{Battlesnake, Board, Coord, Game, GameInfo, Ruleset};
 
-use mm_search::search;
+use simple::{Movement, SimpleBoard};
+use snakes::Appearance;
+use time::TimeManager;
+use weights::HeuristicWeights;
 //use simple::{SimpleBoard, SimpleSnake};
 
 // info is called when you create your Battlesnake on play.battlesnake.com
 // and controls your Battlesnake's appearance
 // TIP: If you open your Battlesnake URL in a browser you should see this data
-pub fn info() -> Value {
+pub fn info(appearance: &Appearance) -> Value {
     info!("INFO");
 
-    return json!({
+    json!({
         "apiversion": "1",
-        "author": "Group 18", // TODO: Your Battlesnake Username
-        "color": "#e83d84", // TODO: Choose color
-        "head": "tiger-king", // TODO: Choose head
-        "tail": "coffee", // TODO: Choose tail
-    });
+        "author": appearance.author,
+        "color": appearance.color,
+        "head": appearance.head,
+        "tail": appearance.tail,
+    })
 }
 
 // start is called when your Battlesnake begins a game
 pub fn start(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
     // create team mate pairs
     // store timeout
-    info!("GAME START");
+    info!(
+        "GAME START {} for {}: {} on map {} from {}",
+        _game.id, _you.name, _game.ruleset.name, _game.map, _game.source
+    );
 }
 
 // end is called when your Battlesnake finishes a game
 pub fn end(_game: &Game, _turn: &i32, _board: &Board, _you: &Battlesnake) {
-    info!("GAME OVER");
+    info!("GAME OVER {} for {} after {} turns", _game.id, _you.name, _turn);
+}
+
+// Which layer of get_move produced the answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveSource {
+    // A teammate's search already picked our move this turn
+    Cache,
+    Search,
+    // The search failed or ran out of time, the move that keeps the most room
+    LargestArea,
+    // Nothing else worked, any move that stays on the board
+    LastResort,
+}
+
+impl std::fmt::Display for MoveSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let str = match self {
+            MoveSource::Cache => "cache",
+            MoveSource::Search => "search",
+            MoveSource::LargestArea => "largest area",
+            MoveSource::LastResort => "last resort",
+        };
+        write!(f, "{}", str)
+    }
 }
 
 // move is called on every turn and returns your next move
 // Valid moves are "up", "down", "left", or "right"
 // See https://docs.battlesnake.com/api/example-move for available data
+// The weights only apply to this move, the game keeps its own
 pub fn get_move(
     _game: &Game,
     turn: &i32,
     _board: &Board,
     you: &Battlesnake,
     game_info: &mut GameInfo,
+    weights: HeuristicWeights,
+    arrived: Instant,
 ) -> Value {
-    let my_id = you.id.clone();
-    let team_idx = game_info
-        .agent_ids
-        .iter()
-        .position(|x| x == &my_id)
-        .expect("Agent ID not found");
+    let (chosen, source) = choose_move(*turn, _board, you, game_info, weights, arrived);
+    info!("MOVE {}: {} from {}", turn, chosen, source);
+    json!({ "move": chosen })
+}
+
+// Never fails, every layer that cannot answer hands over to a cheaper one
+fn choose_move(
+    turn: i32,
+    board: &Board,
+    you: &Battlesnake,
+    game_info: &mut GameInfo,
+    weights: HeuristicWeights,
+    arrived: Instant,
+) -> (Movement, MoveSource) {
+    let team_idx = match game_info.agent_ids.iter().position(|x| x == &you.id) {
+        Some(idx) => idx,
+        None => {
+            game_info.add_agent(&you.id, turn);
+            game_info.agent_ids.len() - 1
+        }
+    };
+
+    let timeout = Duration::from_millis(game_info.timeout as u64);
+    let latency = &mut game_info.agent_latency[team_idx];
+    latency.observe(&you.latency, timeout, turn);
+    let time = TimeManager::new(arrived, timeout, latency.margin());
+
+    let cached = game_info.agent_moves[team_idx].get(turn as usize).copied();
+    let answer = match cached {
+        Some(mv) => (mv, MoveSource::Cache),
+        None => search_or_fallback(turn, board, you, game_info, weights, &time),
+    };
+    game_info.agent_latency[team_idx].answered(time.elapsed());
+    answer
+}
 
-    if game_info.agent_moves[team_idx].len() == *turn as usize + 1 {
-        return json!({ "move": game_info.agent_moves[team_idx][*turn as usize] });
+fn search_or_fallback(
+    turn: i32,
+    board: &Board,
+    you: &Battlesnake,
+    game_info: &mut GameInfo,
+    weights: HeuristicWeights,
+    time: &TimeManager,
+) -> (Movement, MoveSource) {
+    // The search runs on a copy with the weights of this move, so a panic
+    // can not leave anything half done in the game we keep
+    let searched_info = GameInfo {
+        weights,
+        ..game_info.clone()
+    };
+    // A panic in a search thread is a failed search like any other
+    let searched = panic::catch_unwind(AssertUnwindSafe(|| {
+        searched_info.engine.search(board, &searched_info, time)
+    }))
+    .unwrap_or_else(|payload| Err(format!("search panicked: {}", panic_message(&*payload))));
+    let (moves, source) = match searched {
+        Ok(result) => {
+            info!("Search: {}", result.summary());
+            (result.moves, MoveSource::Search)
+        }
+        Err(e) => {
+            warn!("Search failed, falling back to the largest area: {}", e);
+            let largest = panic::catch_unwind(AssertUnwindSafe(|| {
+                SimpleBoard::from(board, &searched_info).largest_area_move()
+            }))
+            .unwrap_or_else(|payload| {
+                warn!("Largest area panicked: {}", panic_message(&*payload));
+                Vec::new()
+            });
+            (largest, MoveSource::LargestArea)
+        }
+    };
+
+    // store the moves of team mates that have not moved yet this turn
+    for (agent_idx, agent_id) in game_info.agent_ids.iter().enumerate() {
+        if game_info.agent_moves[agent_idx].len() != turn as usize {
+            continue;
+        }
+        let board_idx = board.snakes.iter().position(|s| &s.id == agent_id);
+        if let Some(mv) = board_idx.and_then(|idx| moves.iter().find(|mv| mv.id == idx)) {
+            game_info.agent_moves[agent_idx].push(mv.mv);
+        }
     }
 
-    let moves = search(_board, &game_info);
+    let board_idx = board.snakes.iter().position(|s| s.id == you.id);
+    match board_idx.and_then(|idx| moves.iter().find(|mv| mv.id == idx)) {
+        Some(mv) => (mv.mv, source),
+        None => {
+            warn!("No move found for {}, taking any move that stays on the board", you.id);
+            (last_resort(board, you, &game_info.ruleset), MoveSource::LastResort)
+        }
+    }
+}
 
-    let teammate_id = game_info.agent_ids[1 - team_idx].clone();
-    let board_idx = _board.snakes.iter().position(|s| s.id == my_id).unwrap();
-    let chosen = moves.iter().find(|mv| mv.id == board_idx).unwrap().mv;
-    game_info.agent_moves[team_idx].push(chosen);
-    let teammate_idx =  _board.snakes.iter().position(|s| s.id == teammate_id);
-    if !teammate_idx.is_none() {
-        game_info.agent_moves[1 - team_idx]
-            .push(moves.iter().find(|mv| mv.id == teammate_idx.unwrap()).unwrap().mv);
+// The message a panic was started with
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown panic", |m| m.as_str()),
     }
+}
 
-    info!("MOVE {}: {}", turn, chosen);
-    // store down for team mate
-    return json!({ "move": chosen });
+// The first move the rules do not eliminate us for while the other snakes
+// keep going straight, up if there is none
+fn last_resort(board: &Board, you: &Battlesnake, ruleset: &Ruleset) -> Movement {
+    let settings = rules::Settings::from(ruleset);
+    let mut board = board.clone();
+    if !board.snakes.iter().any(|s| s.id == you.id) {
+        board.snakes.push(you.clone());
+    }
+    Movement::all()
+        .into_iter()
+        .find(|&mv| {
+            let mut next = board.clone();
+            let moves = HashMap::from([(you.id.clone(), mv)]);
+            let eliminations = rules::step(&mut next, &moves, &settings, &mut rand::thread_rng());
+            match eliminations.iter().find(|e| e.id == you.id) {
+                Some(e) => {
+                    debug!("Last resort {} is eliminated: {:?} by {:?}", mv, e.cause, e.by);
+                    false
+                }
+                None => true,
+            }
+        })
+        .unwrap_or(Movement::Up)
 }
 
 impl Hash for Board {
@@ -122,3 +268,153 @@ impl PartialEq for Battlesnake {
 }
 
 impl Eq for Battlesnake {}
+
+#[cfg(test)]
+mod tests {
+    use super::{choose_move, last_resort, panic_message, MoveSource};
+    use crate::logic::weights::HeuristicWeights;
+    use crate::logic::simple::Movement;
+    use crate::{Battlesnake, Board, Coord, GameInfo, Ruleset};
+    use std::time::{Duration, Instant};
+
+    fn snake(id: &str, body: &[(i32, i32)]) -> Battlesnake {
+        let body: Vec<Coord> = body.iter().map(|&(x, y)| Coord { x, y }).collect();
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: String::from("0"),
+            shout: None,
+            squad: String::new(),
+            customizations: Default::default(),
+        }
+    }
+
+    fn game_info(timeout: u32) -> GameInfo {
+        GameInfo {
+            id: String::from("game"),
+            timeout,
+            ruleset: Ruleset::default(),
+            agent_ids: vec![String::from("us")],
+            agent_moves: vec![vec![]],
+            agent_latency: vec![Default::default()],
+            weights: Default::default(),
+            engine: Default::default(),
+        }
+    }
+
+    // Going left leads into a dead end, same position as in mm_search
+    fn dead_end_board() -> Board {
+        Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        }
+    }
+
+    #[test]
+    fn test_expired_search_falls_back() {
+        let board = dead_end_board();
+        let mut game_info = game_info(0);
+        let weights = HeuristicWeights {
+            length: 0,
+            ..Default::default()
+        };
+        let answer = choose_move(0, &board, &board.snakes[0], &mut game_info, weights, Instant::now());
+        assert_eq!(answer, (Movement::Right, MoveSource::LargestArea));
+        assert_eq!(game_info.agent_moves[0], vec![Movement::Right]);
+        // The weights of the move are not kept
+        assert_eq!(game_info.weights, HeuristicWeights::default());
+    }
+
+    #[test]
+    fn test_cached_move() {
+        let board = dead_end_board();
+        let mut game_info = game_info(0);
+        game_info.agent_moves[0] = vec![Movement::Up, Movement::Left];
+        let answer = choose_move(1, &board, &board.snakes[0], &mut game_info, Default::default(), Instant::now());
+        assert_eq!(answer, (Movement::Left, MoveSource::Cache));
+    }
+
+    #[test]
+    fn test_waiting_is_not_network() {
+        // The request waited 300 ms for the search of a teammate before it
+        // got the cached move
+        let board = dead_end_board();
+        let mut game_info = game_info(500);
+        game_info.agent_moves[0] = vec![Movement::Up, Movement::Left];
+        let arrived = Instant::now() - Duration::from_millis(300);
+        choose_move(1, &board, &board.snakes[0], &mut game_info, Default::default(), arrived);
+
+        // The engine measured 320 ms, only 20 of them were the network
+        game_info.agent_latency[0].observe("320", Duration::from_millis(500), 2);
+        assert!(game_info.agent_latency[0].margin() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_last_resort() {
+        // A snake that is not on the board gets a move that stays on the board
+        let board = dead_end_board();
+        let mut game_info = game_info(0);
+        let ghost = snake("ghost", &[(6, 6), (5, 6)]);
+        let answer = choose_move(3, &board, &ghost, &mut game_info, Default::default(), Instant::now());
+        assert_eq!(answer, (Movement::Down, MoveSource::LastResort));
+        assert_eq!(game_info.agent_ids, vec!["us", "ghost"]);
+    }
+
+    #[test]
+    fn test_last_resort_wrapped() {
+        // Only the way across the left edge is free
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(0, 3), (1, 3), (1, 4), (0, 4), (0, 5)]),
+                snake("them", &[(3, 2), (2, 2), (1, 2), (0, 2), (0, 1)]),
+            ],
+            hazards: vec![],
+        };
+        let wrapped = Ruleset {
+            name: String::from("wrapped"),
+            ..Default::default()
+        };
+        assert_eq!(last_resort(&board, &board.snakes[0], &wrapped), Movement::Left);
+        assert_eq!(last_resort(&board, &board.snakes[0], &Ruleset::default()), Movement::Up);
+    }
+
+    #[test]
+    fn test_last_resort_enemy_straight() {
+        // Up is free now, but the longer enemy keeps going down into it
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(3, 3), (3, 2), (3, 1)]),
+                snake("them", &[(3, 5), (3, 6), (4, 6), (5, 6)]),
+            ],
+            hazards: vec![],
+        };
+        assert_eq!(last_resort(&board, &board.snakes[0], &Ruleset::default()), Movement::Left);
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("no move for {}", "us")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "no move for us");
+        let payload = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static");
+    }
+}
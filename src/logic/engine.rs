@@ -0,0 +1,169 @@
+// The search engines that can pick our moves. SNAKE_ENGINE or the config of
+// a snake selects one at runtime so they can be compared in self-play.
+
+use crate::logic::mcts::{self, GreedyRollout, RandomRollout};
+use crate::logic::mm_search::{self, SearchMode};
+use crate::logic::simple::{SimpleBoard, SnakeMove};
+use crate::logic::time::TimeManager;
+use crate::{Board, GameInfo};
+use serde::{Deserialize, Deserializer};
+
+// What a search found, the chosen move and how it got there
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    // Our joint move
+    pub moves: Vec<SnakeMove>,
+    // Principal variation from the root, the joint move of every ply and the
+    // board after it. Empty if nothing was searched, it ends early where the
+    // search reused a transposition table entry
+    pub pv: Vec<(Vec<SnakeMove>, SimpleBoard)>,
+    pub value: i32,
+    // Turns searched
+    pub depth: i32,
+    pub nodes: u64,
+}
+
+impl SearchResult {
+    // Result of a move that was not searched, e.g. the only safe one
+    pub fn unsearched(moves: Vec<SnakeMove>) -> Self {
+        SearchResult {
+            moves,
+            pv: Vec::new(),
+            value: 0,
+            depth: 0,
+            nodes: 0,
+        }
+    }
+
+    // One line for the move log, e.g. "depth 3, 1520 nodes, value 42, pv 0:up | 1:left | 0:up"
+    pub fn summary(&self) -> String {
+        let pv: Vec<String> = self
+            .pv
+            .iter()
+            .map(|(moves, _)| {
+                moves
+                    .iter()
+                    .map(|m| format!("{}:{}", m.id, m.mv))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        format!(
+            "depth {}, {} nodes, value {}, pv {}",
+            self.depth,
+            self.nodes,
+            self.value,
+            pv.join(" | ")
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    // Paranoid minimax of our team against everyone else
+    #[default]
+    Minimax,
+    // Minimax where both sides move at once, every turn is a matrix game
+    Simultaneous,
+    // Decoupled UCT with random rollouts
+    Mcts,
+    // Decoupled UCT with greedy rollouts
+    MctsGreedy,
+}
+
+impl Engine {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "minimax" => Some(Engine::Minimax),
+            "simultaneous" => Some(Engine::Simultaneous),
+            "mcts" => Some(Engine::Mcts),
+            "mcts-greedy" => Some(Engine::MctsGreedy),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        std::env::var("SNAKE_ENGINE")
+            .ok()
+            .and_then(|name| Engine::from_name(&name))
+            .unwrap_or(Engine::Minimax)
+    }
+
+    // Fails if the deadline passed before anything was searched
+    pub fn search(
+        self,
+        board: &Board,
+        game_info: &GameInfo,
+        time: &TimeManager,
+    ) -> Result<SearchResult, String> {
+        match self {
+            Engine::Minimax => mm_search::search(board, game_info, SearchMode::Alternating, time),
+            Engine::Simultaneous => mm_search::search(board, game_info, SearchMode::Simultaneous, time),
+            Engine::Mcts => mcts::search(board, game_info, &RandomRollout, time),
+            Engine::MctsGreedy => mcts::search(board, game_info, &GreedyRollout, time),
+        }
+    }
+}
+
+// Engines are named the same way as in SNAKE_ENGINE
+impl<'de> Deserialize<'de> for Engine {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Engine::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown engine {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Engine, SearchResult};
+    use crate::logic::simple::{Movement, SimpleBoard, SnakeMove};
+    use crate::{Board, GameInfo, Ruleset};
+
+    #[test]
+    fn test_engine_names() {
+        assert_eq!(Engine::from_name("minimax"), Some(Engine::Minimax));
+        assert_eq!(Engine::from_name("simultaneous"), Some(Engine::Simultaneous));
+        assert_eq!(Engine::from_name("MCTS"), Some(Engine::Mcts));
+        assert_eq!(Engine::from_name("mcts-greedy"), Some(Engine::MctsGreedy));
+        assert_eq!(Engine::from_name("alphabeta"), None);
+        assert_eq!(serde_json::from_str::<Engine>(r#""mcts""#).unwrap(), Engine::Mcts);
+        assert!(serde_json::from_str::<Engine>(r#""alphabeta""#).is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let board = Board {
+            height: 3,
+            width: 3,
+            food: vec![],
+            snakes: vec![],
+            hazards: vec![],
+        };
+        let game_info = GameInfo {
+            id: String::from("game"),
+            timeout: 100,
+            ruleset: Ruleset::default(),
+            agent_ids: vec![],
+            agent_moves: vec![],
+            agent_latency: vec![],
+            weights: Default::default(),
+            engine: Default::default(),
+        };
+        let board = SimpleBoard::from(&board, &game_info);
+
+        let mut result = SearchResult::unsearched(vec![SnakeMove { id: 0, mv: Movement::Up }]);
+        assert_eq!(result.summary(), "depth 0, 0 nodes, value 0, pv ");
+        result.pv = vec![
+            (result.moves.clone(), board.clone()),
+            (
+                vec![SnakeMove { id: 1, mv: Movement::Left }, SnakeMove { id: 2, mv: Movement::Down }],
+                board,
+            ),
+        ];
+        result.depth = 1;
+        result.nodes = 17;
+        result.value = -5;
+        assert_eq!(result.summary(), "depth 1, 17 nodes, value -5, pv 0:up | 1:left 2:down");
+    }
+}
@@ -0,0 +1,406 @@
+// Monte Carlo Tree Search with decoupled UCT for simultaneous moves, see
+// "Monte Carlo Tree Search in Simultaneous Move Games" (Lanctot et al.)
+//
+// Every node is a position where both sides move at the same time. Each side
+// keeps its own statistics per joint move of the side and picks its move
+// with UCB1 without knowing the choice of the other side, the children are
+// keyed by the pair of choices. Values are win rates for our team in [0, 1].
+
+use crate::logic::engine::SearchResult;
+use crate::logic::simple::{SimpleBoard, SnakeMove};
+use crate::logic::time::TimeManager;
+use crate::{Board, GameInfo};
+use log::info;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+
+// Exploration constant of UCB1
+const EXPLORATION: f64 = 1.4;
+// Turns played in a rollout before the position is evaluated
+const ROLLOUT_TURNS: usize = 20;
+// Heuristic difference that counts as a clear advantage when a rollout is cut short
+const HEURISTIC_SCALE: f64 = 100.0;
+
+// Picks a joint move for one side during rollouts
+pub trait RolloutPolicy {
+    fn choose(
+        &self,
+        board: &SimpleBoard,
+        our_team: bool,
+        options: &[Vec<SnakeMove>],
+        rng: &mut StdRng,
+    ) -> usize;
+}
+
+// Any safe move, uniformly at random
+pub struct RandomRollout;
+
+impl RolloutPolicy for RandomRollout {
+    fn choose(
+        &self,
+        _board: &SimpleBoard,
+        _our_team: bool,
+        options: &[Vec<SnakeMove>],
+        rng: &mut StdRng,
+    ) -> usize {
+        rng.gen_range(0..options.len())
+    }
+}
+
+// The move with the best fast heuristic for the side, ties broken at random
+pub struct GreedyRollout;
+
+impl RolloutPolicy for GreedyRollout {
+    fn choose(
+        &self,
+        board: &SimpleBoard,
+        our_team: bool,
+        options: &[Vec<SnakeMove>],
+        rng: &mut StdRng,
+    ) -> usize {
+        let values: Vec<i32> = options
+            .iter()
+            .map(|m| {
+                let h = board.simulate_joint(m).heuristic(true);
+                if our_team {
+                    h
+                } else {
+                    h.saturating_neg()
+                }
+            })
+            .collect();
+        let best = *values.iter().max().unwrap();
+        let candidates: Vec<usize> = (0..options.len()).filter(|&i| values[i] == best).collect();
+        candidates[rng.gen_range(0..candidates.len())]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Stats {
+    visits: u32,
+    value: f64,
+}
+
+struct Node {
+    board: SimpleBoard,
+    ours: Vec<Vec<SnakeMove>>,
+    theirs: Vec<Vec<SnakeMove>>,
+    our_stats: Vec<Stats>,
+    their_stats: Vec<Stats>,
+    visits: u32,
+    children: HashMap<(usize, usize), usize>,
+}
+
+impl Node {
+    fn new(board: SimpleBoard) -> Self {
+        let ours = board.side_moves(true);
+        let theirs = board.side_moves(false);
+        Node {
+            our_stats: vec![Stats::default(); ours.len()],
+            their_stats: vec![Stats::default(); theirs.len()],
+            board,
+            ours,
+            theirs,
+            visits: 0,
+            children: HashMap::new(),
+        }
+    }
+}
+
+// Win rate of a finished game, None while both sides are alive
+fn outcome(board: &SimpleBoard) -> Option<f64> {
+    match board.heuristic(true) {
+        i32::MIN => Some(0.0),
+        i32::MAX => Some(1.0),
+        _ => None,
+    }
+}
+
+// Maps the heuristic of an unfinished game to an estimated win rate
+fn estimate(board: &SimpleBoard) -> f64 {
+    outcome(board).unwrap_or_else(|| {
+        let h = board.heuristic(true) as f64;
+        0.5 + 0.5 * (h / HEURISTIC_SCALE).tanh()
+    })
+}
+
+// The most visited move of one side
+fn most_visited(stats: &[Stats]) -> usize {
+    (0..stats.len()).max_by_key(|&i| stats[i].visits).unwrap()
+}
+
+// UCB1, unvisited moves first
+fn select(stats: &[Stats], visits: u32) -> usize {
+    let log_visits = (visits.max(1) as f64).ln();
+    let mut best = 0;
+    let mut best_value = f64::MIN;
+    for (i, s) in stats.iter().enumerate() {
+        if s.visits == 0 {
+            return i;
+        }
+        let n = s.visits as f64;
+        let value = s.value / n + EXPLORATION * (log_visits / n).sqrt();
+        if value > best_value {
+            best = i;
+            best_value = value;
+        }
+    }
+    best
+}
+
+pub struct Tree<'a, P: RolloutPolicy> {
+    nodes: Vec<Node>,
+    policy: &'a P,
+    rng: StdRng,
+}
+
+impl<'a, P: RolloutPolicy> Tree<'a, P> {
+    pub fn new(board: SimpleBoard, policy: &'a P, rng: StdRng) -> Self {
+        Tree {
+            nodes: vec![Node::new(board)],
+            policy,
+            rng,
+        }
+    }
+
+    // One selection, expansion, rollout and backpropagation
+    pub fn iterate(&mut self) {
+        let mut path: Vec<(usize, usize, usize)> = Vec::new();
+        let mut current = 0;
+        let value = loop {
+            let node = &self.nodes[current];
+            if let Some(v) = outcome(&node.board) {
+                break v;
+            }
+            let a = select(&node.our_stats, node.visits);
+            let b = select(&node.their_stats, node.visits);
+            path.push((current, a, b));
+            if let Some(&child) = node.children.get(&(a, b)) {
+                current = child;
+                continue;
+            }
+
+            let moves: Vec<SnakeMove> = node.ours[a].iter().chain(node.theirs[b].iter()).copied().collect();
+            let next_board = node.board.simulate_joint(&moves);
+            let value = self.rollout(next_board.clone());
+            let child = self.nodes.len();
+            self.nodes.push(Node::new(next_board));
+            self.nodes[current].children.insert((a, b), child);
+            break value;
+        };
+
+        for (n, a, b) in path {
+            let node = &mut self.nodes[n];
+            node.visits += 1;
+            node.our_stats[a].visits += 1;
+            node.our_stats[a].value += value;
+            node.their_stats[b].visits += 1;
+            node.their_stats[b].value += 1.0 - value;
+        }
+    }
+
+    fn rollout(&mut self, mut board: SimpleBoard) -> f64 {
+        for _ in 0..ROLLOUT_TURNS {
+            if let Some(v) = outcome(&board) {
+                return v;
+            }
+            let ours = board.side_moves(true);
+            let theirs = board.side_moves(false);
+            let a = self.policy.choose(&board, true, &ours, &mut self.rng);
+            let b = self.policy.choose(&board, false, &theirs, &mut self.rng);
+            let moves: Vec<SnakeMove> = ours[a].iter().chain(theirs[b].iter()).copied().collect();
+            board = board.simulate_joint(&moves);
+        }
+        estimate(&board)
+    }
+
+    // Our most visited joint move at the root
+    pub fn best_move(&self) -> Vec<SnakeMove> {
+        let root = &self.nodes[0];
+        root.ours[most_visited(&root.our_stats)].clone()
+    }
+
+    // Win rate of our most visited move at the root
+    pub fn best_value(&self) -> f64 {
+        let root = &self.nodes[0];
+        let stats = root.our_stats[most_visited(&root.our_stats)];
+        stats.value / stats.visits.max(1) as f64
+    }
+
+    // Follows the most visited move of both sides as long as the tree goes
+    pub fn principal_variation(&self) -> Vec<(Vec<SnakeMove>, SimpleBoard)> {
+        let mut pv = Vec::new();
+        let mut node = &self.nodes[0];
+        while node.visits > 0 {
+            let a = most_visited(&node.our_stats);
+            let b = most_visited(&node.their_stats);
+            let Some(&child) = node.children.get(&(a, b)) else {
+                break;
+            };
+            let moves: Vec<SnakeMove> = node.ours[a].iter().chain(node.theirs[b].iter()).copied().collect();
+            node = &self.nodes[child];
+            pv.push((moves, node.board.clone()));
+        }
+        pv
+    }
+
+    pub fn root_visits(&self) -> u32 {
+        self.nodes[0].visits
+    }
+}
+
+pub fn search<P: RolloutPolicy>(
+    board: &Board,
+    game_info: &GameInfo,
+    policy: &P,
+    time: &TimeManager,
+) -> Result<SearchResult, String> {
+    let simple_board = SimpleBoard::from(board, game_info);
+    search_tree(Tree::new(simple_board, policy, StdRng::from_entropy()), time)
+}
+
+fn search_tree<P: RolloutPolicy>(mut tree: Tree<P>, time: &TimeManager) -> Result<SearchResult, String> {
+    if tree.nodes[0].ours.len() == 1 {
+        return Ok(SearchResult::unsearched(tree.best_move()));
+    }
+    // Iterations stop at a finished game, at the root not one would finish
+    if let Some(v) = outcome(&tree.nodes[0].board) {
+        return Ok(SearchResult {
+            value: (v * 1000.0).round() as i32,
+            ..SearchResult::unsearched(tree.best_move())
+        });
+    }
+    while !time.expired() {
+        tree.iterate();
+    }
+    if tree.root_visits() == 0 {
+        return Err(format!("no iteration finished after {:?}", time.elapsed()));
+    }
+    let best = tree.best_move();
+    info!(
+        "MCTS {} iterations after {:?}: {:?}",
+        tree.root_visits(),
+        time.elapsed(),
+        best
+    );
+    // The value is the win rate in per mille, the depth how far the tree
+    // agrees on what both sides play
+    let pv = tree.principal_variation();
+    Ok(SearchResult {
+        moves: best,
+        value: (tree.best_value() * 1000.0).round() as i32,
+        depth: pv.len() as i32,
+        nodes: tree.nodes.len() as u64,
+        pv,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{search_tree, GreedyRollout, RandomRollout, RolloutPolicy, Tree};
+    use crate::logic::time::TimeManager;
+    use crate::logic::simple::{Movement, SimpleBoard};
+    use crate::{Battlesnake, Board, Coord, GameInfo, Ruleset};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::time::{Duration, Instant};
+
+    fn snake(id: &str, body: &[(i32, i32)]) -> Battlesnake {
+        let body: Vec<Coord> = body.iter().map(|&(x, y)| Coord { x, y }).collect();
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: String::from("0"),
+            shout: None,
+            squad: String::new(),
+            customizations: Default::default(),
+        }
+    }
+
+    fn dead_end_board() -> SimpleBoard {
+        // Going left leads into a dead end, same position as in mm_search
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        };
+        let game_info = GameInfo {
+            id: String::from("game"),
+            timeout: 100,
+            ruleset: Ruleset::default(),
+            agent_ids: vec![String::from("us")],
+            agent_moves: vec![vec![]],
+            agent_latency: vec![Default::default()],
+            weights: Default::default(),
+            engine: Default::default(),
+        };
+        SimpleBoard::from(&board, &game_info)
+    }
+
+    fn best_after<P: RolloutPolicy>(policy: &P, iterations: u32) -> Movement {
+        let mut tree = Tree::new(dead_end_board(), policy, StdRng::seed_from_u64(1));
+        for _ in 0..iterations {
+            tree.iterate();
+        }
+        assert_eq!(tree.root_visits(), iterations);
+        tree.best_move()[0].mv
+    }
+
+    #[test]
+    fn test_random_rollout_avoids_dead_end() {
+        assert_eq!(best_after(&RandomRollout, 2000), Movement::Right);
+    }
+
+    #[test]
+    fn test_greedy_rollout_avoids_dead_end() {
+        assert_eq!(best_after(&GreedyRollout, 500), Movement::Right);
+    }
+
+    #[test]
+    fn test_finished_root() {
+        // The enemy is already eliminated, there is nothing to iterate
+        let mut board = dead_end_board();
+        board.snakes[1] = None;
+        let tree = Tree::new(board, &RandomRollout, StdRng::seed_from_u64(1));
+        let time = TimeManager::new(Instant::now(), Duration::from_millis(20), Duration::ZERO);
+        let result = search_tree(tree, &time).unwrap();
+        assert_eq!(result.value, 1000);
+        assert_eq!(result.moves.len(), 1);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let mut tree = Tree::new(dead_end_board(), &RandomRollout, StdRng::seed_from_u64(1));
+        for _ in 0..500 {
+            tree.iterate();
+        }
+        let pv = tree.principal_variation();
+        assert!(!pv.is_empty());
+        assert_eq!(pv[0].0[0].mv, tree.best_move()[0].mv);
+        assert_eq!(
+            pv[0].1.zobrist_hash(true),
+            dead_end_board().simulate_joint(&pv[0].0).zobrist_hash(true)
+        );
+        assert!((0.0..=1.0).contains(&tree.best_value()));
+    }
+
+    #[test]
+    fn test_same_seed_same_tree() {
+        let a = best_after(&RandomRollout, 300);
+        let b = best_after(&RandomRollout, 300);
+        assert_eq!(a, b);
+    }
+}
@@ -1,186 +1,958 @@
 use crate::{Board, /*Coord,*/ GameInfo};
-use log::info;
-use std::time::Instant;
+use log::{debug, info};
+use std::thread;
 
-// Define a tree node that can have many children
-#[derive(Debug)]
-struct TreeNode {
-    value: i32,
-    children: Vec<TreeNode>,
+use crate::logic::simple::SimpleBoard;
+
+use super::engine::SearchResult;
+use super::simple::SnakeMove;
+use super::time::TimeManager;
+use super::transposition::{Bound, TTEntry, TranspositionTable};
+use super::zobrist::{self, MAX_SNAKES};
+use std::cmp::Reverse;
+
+// Upper bound on the number of turns searched, deeper than any real time budget allows
+const MAX_TURNS: i32 = 64;
+// The transposition tables hold 2^18 entries in total, split between the threads
+const TT_SIZE_LOG2: u32 = 18;
+
+// Joint moves of a principal variation, one per ply
+type Line = Vec<Vec<SnakeMove>>;
+
+// How a turn is searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    // Our team moves first and the opponents reply knowing our move, one ply each
+    Alternating,
+    // Both sides move at once and collisions are resolved after the joint move,
+    // every turn is a matrix game solved with maximin over our joint moves
+    Simultaneous,
 }
 
-impl TreeNode {
-    fn new(value: i32) -> Self {
-        TreeNode {
-            value,
-            children: Vec::new(),
+// How the children of a node are ordered before they are searched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveOrdering {
+    // Sorted by the fast heuristic of every child
+    Heuristic,
+    // The transposition table move first, then killer moves and the history table
+    Killers,
+}
+
+// State shared by every node searched by one thread
+struct SearchContext {
+    time: TimeManager,
+    tt: TranspositionTable,
+    mode: SearchMode,
+    ordering: MoveOrdering,
+    // Two moves per depth that caused a cutoff
+    killers: Vec<[Option<u64>; 2]>,
+    // Cutoffs caused by each move of each snake, weighted by remaining depth
+    history: [[i32; 5]; MAX_SNAKES],
+    nodes: u64,
+}
+
+impl SearchContext {
+    fn new(time: TimeManager, tt_size_log2: u32, mode: SearchMode, ordering: MoveOrdering) -> Self {
+        SearchContext {
+            time,
+            tt: TranspositionTable::new(tt_size_log2),
+            mode,
+            ordering,
+            killers: Vec::new(),
+            history: [[0; 5]; MAX_SNAKES],
+            nodes: 0,
         }
     }
 
-    fn add_child(&mut self, child: TreeNode) {
-        //info!("Adding child with value: {}", child.value);
-        self.children.push(child);
+    fn is_killer(&self, depth: i32, key: u64) -> bool {
+        self.killers
+            .get(depth as usize)
+            .is_some_and(|k| k.contains(&Some(key)))
     }
 
-    fn print(&self, prefix: String, is_last: bool) {
-        println!(
-            "{}{}─ {}",
-            prefix,
-            if is_last { "└" } else { "├" },
-            self.value
-        );
-        let new_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+    fn history_score(&self, moves: &[SnakeMove]) -> i32 {
+        moves
+            .iter()
+            .map(|m| self.history[m.id][m.mv as usize])
+            .sum()
+    }
 
-        let last_index = self.children.len().saturating_sub(1);
-        for (i, child) in self.children.iter().enumerate() {
-            child.print(new_prefix.clone(), i == last_index);
+    fn record_cutoff(&mut self, depth: i32, remaining: i32, moves: &[SnakeMove]) {
+        let key = move_key(moves);
+        let depth = depth as usize;
+        if self.killers.len() <= depth {
+            self.killers.resize(depth + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[depth];
+        if killers[0] != Some(key) {
+            killers[1] = killers[0];
+            killers[0] = Some(key);
+        }
+        for m in moves {
+            self.history[m.id][m.mv as usize] += remaining * remaining;
         }
     }
 }
 
-use crate::logic::simple::SimpleBoard;
+// Identifies a joint move, the same moves always give the same key. Every
+// snake has its own three bits, so joint moves of up to MAX_SNAKES snakes
+// never share a key
+fn move_key(moves: &[SnakeMove]) -> u64 {
+    moves.iter().fold(0, |key, m| {
+        assert!(m.id < MAX_SNAKES, "no move key for snake {}", m.id);
+        key | (m.mv as u64 + 1) << (3 * m.id)
+    })
+}
 
-use super::simple::SnakeMove;
+// Orders children for MoveOrdering::Killers, cheap enough to skip evaluating them
+fn order_moves(
+    simulations: &mut [(Vec<SnakeMove>, SimpleBoard)],
+    depth: i32,
+    tt_move: Option<u64>,
+    ctx: &SearchContext,
+) {
+    simulations.sort_by_cached_key(|(moves, _)| {
+        let key = move_key(moves);
+        if Some(key) == tt_move {
+            Reverse((2, 0))
+        } else if ctx.is_killer(depth, key) {
+            Reverse((1, 0))
+        } else {
+            Reverse((0, ctx.history_score(moves)))
+        }
+    });
+}
 
-pub fn search(board: &Board, game_info: &GameInfo) -> [SnakeMove; 2] {
-    let start = Instant::now();
+// Number of search threads, SNAKE_THREADS overrides the number of cores
+fn thread_count() -> usize {
+    std::env::var("SNAKE_THREADS")
+        .ok()
+        .and_then(|t| t.parse().ok())
+        .filter(|&t| t > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+pub fn search(
+    board: &Board,
+    game_info: &GameInfo,
+    mode: SearchMode,
+    time: &TimeManager,
+) -> Result<SearchResult, String> {
+    search_threads(board, game_info, mode, time, thread_count())
+}
+
+fn search_threads(
+    board: &Board,
+    game_info: &GameInfo,
+    mode: SearchMode,
+    time: &TimeManager,
+    threads: usize,
+) -> Result<SearchResult, String> {
     let simple_board = SimpleBoard::from(board, game_info);
-    let timeout: i32 = game_info.timeout as i32 * 1_000_000; // Convert milliseconds to nanoseconds
-    let mut values = Vec::new();
-    let mut moves = Vec::new();
+    let cells = (simple_board.width * simple_board.height) as usize;
+    if !zobrist::fits(simple_board.snakes.len(), cells) {
+        return Err(format!(
+            "{} snakes on {}x{} is too large to hash",
+            simple_board.snakes.len(),
+            simple_board.width,
+            simple_board.height
+        ));
+    }
+    // In simultaneous mode the root keeps the unmoved board, the opponents
+    // move together with us
+    let mut simulations = match mode {
+        SearchMode::Alternating => simple_board.simulate_move(true),
+        SearchMode::Simultaneous => simple_board
+            .side_moves(true)
+            .into_iter()
+            .map(|m| (m, simple_board.clone()))
+            .collect(),
+    };
+    if simulations.len() == 1 {
+        return Ok(SearchResult::unsearched(simulations.remove(0).0));
+    }
 
-    let mut best_value = i32::MIN;
-    let simulations = simple_board.simulate_move(true);
-    for (i, (move_pair, next_board)) in simulations.iter().enumerate() {
-        let time: i32 = (timeout - start.elapsed().as_nanos() as i32) / (simulations.len() as i32 - i as i32);
-        info!("Move {} time: {} (timeout: {} elapsed: {})", i, time, timeout, start.elapsed().as_nanos());
+    // Each thread keeps its table between iterations, shallower results still help
+    let threads = threads.clamp(1, simulations.len());
+    let tt_size_log2 = TT_SIZE_LOG2 - (threads as u32).ilog2();
+    let mut contexts: Vec<SearchContext> = (0..threads)
+        .map(|_| SearchContext::new(*time, tt_size_log2, mode, MoveOrdering::Killers))
+        .collect();
 
-        let mut root = TreeNode::new(0);
+    // Search one more turn per iteration until the deadline, only fully
+    // searched iterations are trusted
+    let mut best: Option<(i32, i32, Line)> = None;
+    for turns in 1..=MAX_TURNS {
+        let max_depth = match mode {
+            SearchMode::Alternating => turns * 2,
+            SearchMode::Simultaneous => turns,
+        };
+        match search_parallel(&simulations, max_depth, &mut contexts) {
+            Some((idx, value, line)) => {
+                info!(
+                    "Depth {} done after {:?} ({:?} left): {:?} value: {}",
+                    turns,
+                    time.elapsed(),
+                    time.remaining(),
+                    simulations[idx].0,
+                    value
+                );
+                // Search the best move first in the next iteration
+                let best_sim = simulations.remove(idx);
+                simulations.insert(0, best_sim);
+                best = Some((turns, value, line));
+                if value == i32::MAX || value == i32::MIN {
+                    break;
+                }
+            }
+            None => {
+                info!("Depth {} timed out after {:?}", turns, time.elapsed());
+                break;
+            }
+        }
+    }
 
-        // minmax on enemies since this outer loop is on friendly
-        let value = minmax_simple(
-            &next_board,
-            1,
-            false,
-            best_value,
-            i32::MAX,
-            1,
-            10,
-            time,
-            &mut root,
-        ).0;
-        //root.print(format!("{:?}:", move_pair), true);
+    // Not even depth 1 finished, the caller has cheaper ways to pick a move
+    let Some((depth, value, line)) = best else {
+        return Err(format!("depth 1 not finished after {:?}", time.elapsed()));
+    };
+    let pv = replay(&simple_board, line, mode);
+    if let Some((_, last)) = pv.last() {
+        debug!("Principal variation ends in\n{}", last);
+    }
+    Ok(SearchResult {
+        moves: simulations.remove(0).0,
+        pv,
+        value,
+        depth,
+        nodes: contexts.iter().map(|ctx| ctx.nodes).sum(),
+    })
+}
+
+// Plays the principal variation from the root and keeps the board after every ply
+fn replay(board: &SimpleBoard, line: Line, mode: SearchMode) -> Vec<(Vec<SnakeMove>, SimpleBoard)> {
+    let mut board = board.clone();
+    let mut our_team = true;
+    let mut pv = Vec::new();
+    for moves in line {
+        board = match mode {
+            // simulate_move knows what the search did when a side had no safe move
+            SearchMode::Alternating => {
+                let key = move_key(&moves);
+                match board.simulate_move(our_team).into_iter().find(|(m, _)| move_key(m) == key) {
+                    Some((_, next_board)) => next_board,
+                    None => break,
+                }
+            }
+            SearchMode::Simultaneous => board.simulate_joint(&moves),
+        };
+        our_team = !our_team;
+        pv.push((moves, board.clone()));
+    }
+    pv
+}
+
+// Splits the root moves between the threads, move i is searched by thread
+// i % threads. With one thread this is the same as search_root, the lowest
+// index wins ties so the result does not depend on which thread finishes first
+fn search_parallel(
+    simulations: &[(Vec<SnakeMove>, SimpleBoard)],
+    max_depth: i32,
+    contexts: &mut [SearchContext],
+) -> Option<(usize, i32, Line)> {
+    let threads = contexts.len();
+    if threads == 1 {
+        return search_root(simulations, max_depth, &mut contexts[0]);
+    }
+
+    let results: Vec<Option<(usize, i32, Line)>> = thread::scope(|scope| {
+        let handles: Vec<_> = contexts
+            .iter_mut()
+            .enumerate()
+            .map(|(t, ctx)| {
+                // SimpleBoard caches its heuristic in Cells, so every thread gets its own copies
+                let roots: Vec<_> = simulations.iter().skip(t).step_by(threads).cloned().collect();
+                scope.spawn(move || {
+                    search_root(&roots, max_depth, ctx)
+                        .map(|(i, value, line)| (t + i * threads, value, line))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            // Keep the message of a panic in a thread for the caller's log
+            .map(|h| h.join().unwrap_or_else(|payload| std::panic::resume_unwind(payload)))
+            .collect()
+    });
+
+    let mut best: Option<(usize, i32, Line)> = None;
+    for result in results {
+        // Every thread has to finish for the iteration to count
+        let (i, value, line) = result?;
+        if best.as_ref().is_none_or(|&(best_i, best_value, _)| {
+            value > best_value || (value == best_value && i < best_i)
+        }) {
+            best = Some((i, value, line));
+        }
+    }
+    best
+}
+
+// Returns the index of the best root move, its value and principal variation,
+// None if the deadline passed. The root move is ply 0 (turn 0 in simultaneous
+// mode), leaves are evaluated at max_depth
+fn search_root(
+    simulations: &[(Vec<SnakeMove>, SimpleBoard)],
+    max_depth: i32,
+    ctx: &mut SearchContext,
+) -> Option<(usize, i32, Line)> {
+    let mut best: Option<(usize, i32, Line)> = None;
+    let mut best_value = i32::MIN;
+    for (i, (move_pair, next_board)) in simulations.iter().enumerate() {
+        let mut line = Vec::new();
+        let value = match ctx.mode {
+            // minmax on enemies since this outer loop is on friendly
+            SearchMode::Alternating => {
+                let value = minmax_simple(
+                    next_board,
+                    1,
+                    max_depth,
+                    false,
+                    best_value,
+                    i32::MAX,
+                    ctx,
+                    &mut line,
+                )?
+                .0;
+                line.insert(0, move_pair.clone());
+                value
+            }
+            // The line starts with the joint move of both sides
+            SearchMode::Simultaneous => {
+                let beta = i32::MAX;
+                min_reply(next_board, move_pair, 0, max_depth, best_value, beta, ctx, &mut line)?.0
+            }
+        };
         info!("Move {:?} value: {}", move_pair, value);
+        if best.is_none() || value > best_value {
+            best = Some((i, value, line));
+        }
         best_value = best_value.max(value);
-        values.push(value);
-        moves.push(move_pair);
-    }
-    let idx = values
-        .iter()
-        .enumerate()
-        .max_by(|(_, v), (_, v2)| v.cmp(v2))
-        .map(|(i, _)| i)
-        .expect(&format!(
-            "No best move found in values: {:?} for {} moves",
-            values,
-            simulations.len()
-        ));
-    *moves[idx]
+    }
+    best
+}
+
+// The table keeps the best value of the children as the search proved it,
+// the value returned to the parent is averaged with the heuristic of the node
+// afterwards and says nothing about the window
+fn bound(best_value: i32, alpha: i32, beta: i32) -> Bound {
+    if best_value <= alpha {
+        Bound::Upper
+    } else if best_value >= beta {
+        Bound::Lower
+    } else {
+        Bound::Exact
+    }
 }
 
+fn usable(entry: &TTEntry, alpha: i32, beta: i32) -> bool {
+    match entry.bound {
+        Bound::Exact => true,
+        Bound::Lower => entry.value >= beta,
+        Bound::Upper => entry.value <= alpha,
+    }
+}
+
+// The value of a node, the best value of its children averaged with the
+// heuristic of the node by how many plies below it was reached
+fn averaged(best_value: (i32, i32), depth: i32, board: &SimpleBoard) -> (i32, i32) {
+    if best_value.0 == i32::MAX || best_value.0 == i32::MIN {
+        return best_value;
+    }
+    let depth_diff = best_value.1 - depth;
+    ((best_value.0 * depth_diff + board.heuristic(true)) / (depth_diff + 1), best_value.1)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn minmax_simple(
     board: &SimpleBoard,
     depth: i32,
+    max_depth: i32,
     our_team: bool,
     mut alpha: i32,
     mut beta: i32,
-    heuristic_time: i32,
-    return_time: i32,
-    timeout: i32,
-    parent: &mut TreeNode,
-) -> (i32, i32) {
-    let start = Instant::now();
-    let mut node = TreeNode::new(0);
-    if depth == 100 || heuristic_time + return_time >= timeout {
+    ctx: &mut SearchContext,
+    pv: &mut Line,
+) -> Option<(i32, i32)> {
+    if ctx.time.expired() {
+        return None;
+    }
+    ctx.nodes += 1;
+    pv.clear();
+    if depth >= max_depth {
         //info!("Depth {} reached", depth);
-        let h = board.heuristic(false);
-        node.value = h;
-        parent.add_child(node);
-        return (h, depth);
+        return Some((board.heuristic(false), depth));
     }
 
+    // A position searched at least as deep before can be reused if its
+    // bound is good enough for the current window
+    let key = board.zobrist_hash(our_team);
+    let remaining = max_depth - depth;
+    let entry = ctx.tt.get(key);
+    if let Some(entry) = entry {
+        if entry.depth >= remaining && usable(&entry, alpha, beta) {
+            let best_value = (entry.value, depth + entry.reach);
+            return Some(averaged(best_value, depth, board));
+        }
+    }
+    let (alpha_start, beta_start) = (alpha, beta);
+
     let mut simulations = board.simulate_move(our_team);
-    if our_team {
-        simulations.sort_by_key(|s| -s.1.heuristic(true));
-    } else {
-        simulations.sort_by_key(|s| s.1.heuristic(true));
+    match ctx.ordering {
+        MoveOrdering::Heuristic => {
+            if our_team {
+                simulations.sort_by_key(|s| -s.1.heuristic(true));
+            } else {
+                simulations.sort_by_key(|s| s.1.heuristic(true));
+            }
+        }
+        // The best move of an earlier search of the position is the
+        // principal variation of the previous iteration
+        MoveOrdering::Killers => order_moves(&mut simulations, depth, entry.and_then(|e| e.best), ctx),
     }
 
-    if let Some(sim) = simulations.first() {
+    // Only the heuristic ordering knows the first child is the best one
+    if let Some(sim) = simulations.first().filter(|_| ctx.ordering == MoveOrdering::Heuristic) {
         let h = sim.1.heuristic(true);
         if our_team && h == i32::MAX {
             //info!("Found max value at depth {}", depth);
-            node.value = i32::MAX;
-            parent.add_child(node);
-            return (i32::MAX, depth);
+            pv.push(sim.0.clone());
+            return Some((i32::MAX, depth));
         } else if !our_team && h == i32::MIN {
             //info!("Found min value at depth {}", depth);
-            node.value = i32::MIN;
-            parent.add_child(node);
-            return (i32::MIN, depth);
+            pv.push(sim.0.clone());
+            return Some((i32::MIN, depth));
         }
     }
 
     let mut best_value = if our_team { (i32::MIN, depth) } else { (i32::MAX, depth)};
+    let mut best_move = None;
+    let mut child_pv = Vec::new();
 
-    for (idx, (_, next_board)) in simulations.iter().enumerate() {
-        let time_left = timeout - start.elapsed().as_nanos() as i32 - return_time;
-        if time_left <= heuristic_time {
-            best_value = (simulations.first().unwrap().1.heuristic(false), depth+1);
-            break;
-        }
-
-        let iterations_left = simulations.len() as i32 - idx as i32;
-        let time_per_move = time_left / iterations_left;
+    for (moves, next_board) in simulations.iter() {
         let value = minmax_simple(
-            &next_board,
+            next_board,
             depth + 1,
+            max_depth,
             !our_team,
             alpha,
             beta,
-            heuristic_time,
-            return_time,
-            time_per_move,
-            &mut node,
-        );
-        if our_team {
-            if (value.0 > best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1) {
-                best_value = value;
-                alpha = alpha.max(best_value.0);
-                if best_value.0 >= beta {
-                    break;
-                }
-            }
+            ctx,
+            &mut child_pv,
+        )?;
+        let improves = if our_team {
+            (value.0 > best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1)
         } else {
-            if (value.0 < best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1) {
-                best_value = value;
-                beta = beta.max(best_value.0);
-                if best_value.0 <= alpha {
-                    break;
-                }
-            }
+            (value.0 < best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1)
+        };
+        if !improves {
+            continue;
+        }
+        best_value = value;
+        best_move = Some(move_key(moves));
+        pv.clear();
+        pv.push(moves.clone());
+        pv.append(&mut child_pv);
+        let cutoff = if our_team {
+            alpha = alpha.max(best_value.0);
+            best_value.0 >= beta
+        } else {
+            beta = beta.min(best_value.0);
+            best_value.0 <= alpha
+        };
+        if cutoff {
+            ctx.record_cutoff(depth, remaining, moves);
+            break;
         }
     }
 
     //info!("Best value at depth {}: {}", depth, best_value);
-    node.value = best_value.0;
-    parent.add_child(node);
-    if best_value.0 == i32::MAX {
-        return best_value;
+    ctx.tt.store(TTEntry {
+        key,
+        value: best_value.0,
+        depth: remaining,
+        reach: best_value.1 - depth,
+        bound: bound(best_value.0, alpha_start, beta_start),
+        best: best_move,
+    });
+    Some(averaged(best_value, depth, board))
+}
+
+// Value of one turn of the matrix game, our best joint move against the
+// opponents' best reply to it. Collisions are resolved after both sides move
+fn maximin_simple(
+    board: &SimpleBoard,
+    depth: i32,
+    max_depth: i32,
+    mut alpha: i32,
+    beta: i32,
+    ctx: &mut SearchContext,
+    pv: &mut Line,
+) -> Option<(i32, i32)> {
+    if ctx.time.expired() {
+        return None;
     }
-    if best_value.0 == i32::MIN {
-        return best_value;
+    ctx.nodes += 1;
+    pv.clear();
+    let h = board.heuristic(true);
+    if h == i32::MAX || h == i32::MIN {
+        return Some((h, depth));
+    }
+    if depth >= max_depth {
+        return Some((board.heuristic(false), depth));
+    }
+
+    let key = board.zobrist_hash(true);
+    let remaining = max_depth - depth;
+    if let Some(entry) = ctx.tt.get(key) {
+        if entry.depth >= remaining && usable(&entry, alpha, beta) {
+            return Some(averaged((entry.value, depth + entry.reach), depth, board));
+        }
+    }
+    let (alpha_start, beta_start) = (alpha, beta);
+
+    let mut best_value = (i32::MIN, depth);
+    let mut row_pv = Vec::new();
+    for ours in board.side_moves(true) {
+        let value = min_reply(board, &ours, depth, max_depth, alpha, beta, ctx, &mut row_pv)?;
+        if (value.0 > best_value.0) || (value.0 == best_value.0 && value.1 > best_value.1) {
+            best_value = value;
+            std::mem::swap(pv, &mut row_pv);
+            alpha = alpha.max(best_value.0);
+            if best_value.0 >= beta {
+                break;
+            }
+        }
+    }
+
+    ctx.tt.store(TTEntry {
+        key,
+        value: best_value.0,
+        depth: remaining,
+        reach: best_value.1 - depth,
+        bound: bound(best_value.0, alpha_start, beta_start),
+        best: None,
+    });
+    Some(averaged(best_value, depth, board))
+}
+
+// The row of the matrix for one of our joint moves, the worst outcome over
+// every joint move of the opponents. The line starts with the joint move of both sides
+#[allow(clippy::too_many_arguments)]
+fn min_reply(
+    board: &SimpleBoard,
+    ours: &[SnakeMove],
+    depth: i32,
+    max_depth: i32,
+    alpha: i32,
+    mut beta: i32,
+    ctx: &mut SearchContext,
+    pv: &mut Line,
+) -> Option<(i32, i32)> {
+    pv.clear();
+    let mut worst = (i32::MAX, depth + 1);
+    let mut child_pv = Vec::new();
+    for theirs in board.side_moves(false) {
+        let moves: Vec<SnakeMove> = ours.iter().chain(theirs.iter()).copied().collect();
+        let next_board = board.simulate_joint(&moves);
+        let value =
+            maximin_simple(&next_board, depth + 1, max_depth, alpha, beta, ctx, &mut child_pv)?;
+        if (value.0 < worst.0) || (value.0 == worst.0 && value.1 > worst.1) {
+            worst = value;
+            pv.clear();
+            pv.push(moves);
+            pv.append(&mut child_pv);
+            beta = beta.min(worst.0);
+            // Our other moves already guarantee alpha
+            if worst.0 <= alpha {
+                break;
+            }
+        }
+    }
+    Some(worst)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        min_reply, minmax_simple, move_key, search, search_root, search_threads, Bound,
+        MoveOrdering, SearchContext, SearchMode, MAX_SNAKES,
+    };
+    use crate::logic::simple::{Movement, SimpleBoard, SnakeMove};
+    use crate::{Battlesnake, Board, Coord, GameInfo, Ruleset};
+    use crate::logic::time::TimeManager;
+    use std::time::{Duration, Instant};
+
+    fn snake(id: &str, body: &[(i32, i32)]) -> Battlesnake {
+        let body: Vec<Coord> = body.iter().map(|&(x, y)| Coord { x, y }).collect();
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health: 100,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: String::from("0"),
+            shout: None,
+            squad: String::new(),
+            customizations: Default::default(),
+        }
+    }
+
+    fn game_info(timeout: u32) -> GameInfo {
+        GameInfo {
+            id: String::from("game"),
+            timeout,
+            ruleset: Ruleset::default(),
+            agent_ids: vec![String::from("us")],
+            agent_moves: vec![vec![]],
+            agent_latency: vec![Default::default()],
+            weights: Default::default(),
+            engine: Default::default(),
+        }
+    }
+
+    fn budget(timeout: u64) -> TimeManager {
+        TimeManager::new(Instant::now(), Duration::from_millis(timeout), Duration::ZERO)
+    }
+
+    #[test]
+    fn test_avoids_dead_end() {
+        // Going left leads into a dead end
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        };
+        let moves = search(&board, &game_info(100), SearchMode::Alternating, &budget(100)).unwrap().moves;
+        let ours = moves.iter().find(|m| m.id == 0).unwrap();
+        assert_eq!(ours.mv, Movement::Right);
+    }
+
+    #[test]
+    fn test_single_option_returns_immediately() {
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(0, 0), (0, 1), (1, 1), (1, 0), (2, 0)]),
+                snake("them", &[(5, 5), (5, 4), (5, 3)]),
+            ],
+            hazards: vec![],
+        };
+        let result = search(&board, &game_info(0), SearchMode::Alternating, &budget(0)).unwrap();
+        assert_eq!(result.moves[0].mv, Movement::Down);
+        assert_eq!(result.nodes, 0);
+    }
+
+    #[test]
+    fn test_transposition_table_reuse() {
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![Coord { x: 3, y: 3 }],
+            snakes: vec![
+                snake("us", &[(1, 1), (1, 0), (0, 0)]),
+                snake("them", &[(5, 5), (5, 6), (6, 6)]),
+            ],
+            hazards: vec![],
+        };
+        let simple_board = SimpleBoard::from(&board, &game_info(100));
+        let mut ctx = SearchContext::new(budget(60_000), 12, SearchMode::Alternating, MoveOrdering::Killers);
+        let mut pv = Vec::new();
+        let first = minmax_simple(&simple_board, 0, 4, true, i32::MIN, i32::MAX, &mut ctx, &mut pv);
+        let entry = ctx.tt.get(simple_board.zobrist_hash(true)).unwrap();
+        assert_eq!(entry.depth, 4);
+        assert_eq!(entry.bound, Bound::Exact);
+        assert!(!pv.is_empty());
+
+        // The second search is answered straight from the table
+        let nodes = ctx.nodes;
+        let second = minmax_simple(&simple_board, 0, 4, true, i32::MIN, i32::MAX, &mut ctx, &mut pv);
+        assert_eq!(first, second);
+        assert_eq!(ctx.nodes, nodes + 1);
+        assert!(pv.is_empty());
+    }
+
+    #[test]
+    fn test_cutoff_is_not_exact() {
+        // The opponents to move, their best reply is worse for us than the
+        // heuristic of the position so the averaged value lies above it
+        let simple_board = SimpleBoard::from(&midgame_board(), &game_info(100));
+        let key = simple_board.zobrist_hash(false);
+        let mut ctx = SearchContext::new(budget(60_000), 12, SearchMode::Alternating, MoveOrdering::Killers);
+        let mut pv = Vec::new();
+        let full = minmax_simple(&simple_board, 0, 2, false, i32::MIN, i32::MAX, &mut ctx, &mut pv).unwrap();
+        let best = ctx.tt.get(key).unwrap().value;
+        assert!(full.0 > best);
+
+        // The best reply cuts off at alpha, the averaged value falls back into
+        // the window but the stored entry only bounds the position
+        let (alpha, beta) = (best, full.0 + 1);
+        let mut ctx = SearchContext::new(budget(60_000), 12, SearchMode::Alternating, MoveOrdering::Killers);
+        let value = minmax_simple(&simple_board, 0, 2, false, alpha, beta, &mut ctx, &mut pv).unwrap();
+        assert!(value.0 > alpha && value.0 < beta);
+        let entry = ctx.tt.get(key).unwrap();
+        assert_eq!(entry.bound, Bound::Upper);
+        assert!(entry.value <= alpha);
+        // A probe with the same window gives the same value back
+        assert_eq!(minmax_simple(&simple_board, 0, 2, false, alpha, beta, &mut ctx, &mut pv), Some(value));
+    }
+
+    #[test]
+    fn test_parallel_search_agrees() {
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        };
+        for threads in [1, 2, 4] {
+            let moves = search_threads(&board, &game_info(100), SearchMode::Alternating, &budget(100), threads).unwrap().moves;
+            let ours = moves.iter().find(|m| m.id == 0).unwrap();
+            assert_eq!(ours.mv, Movement::Right, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_simultaneous_avoids_dead_end() {
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        };
+        let moves = search_threads(&board, &game_info(100), SearchMode::Simultaneous, &budget(100), 1).unwrap().moves;
+        let ours = moves.iter().find(|m| m.id == 0).unwrap();
+        assert_eq!(ours.mv, Movement::Right);
+    }
+
+    #[test]
+    fn test_simultaneous_head_to_head() {
+        // Both heads can reach (3,3), the longer enemy would win the collision
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(2, 3), (1, 3), (0, 3)]),
+                snake("them", &[(4, 3), (5, 3), (6, 3), (6, 2), (6, 1)]),
+            ],
+            hazards: vec![],
+        };
+        let moves = search_threads(&board, &game_info(100), SearchMode::Simultaneous, &budget(100), 1).unwrap().moves;
+        let ours = moves.iter().find(|m| m.id == 0).unwrap();
+        assert_ne!(ours.mv, Movement::Right);
+
+        // The enemy can reply by moving into (3,3) too, the row loses at once
+        let simple_board = SimpleBoard::from(&board, &game_info(100));
+        let mut ctx = SearchContext::new(budget(60_000), 12, SearchMode::Simultaneous, MoveOrdering::Killers);
+        let right = [SnakeMove { id: 0, mv: Movement::Right }];
+        let mut pv = Vec::new();
+        let value = min_reply(&simple_board, &right, 0, 2, i32::MIN, i32::MAX, &mut ctx, &mut pv);
+        assert_eq!(value, Some((i32::MIN, 1)));
+        // The losing reply is the first joint move of the line
+        assert_eq!(pv[0].len(), 2);
+        assert_eq!(pv[0][0].mv, Movement::Right);
+    }
+
+    #[test]
+    fn test_expired_budget_fails() {
+        // Not even depth 1 finishes, there is no move to trust
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2)]),
+                snake("them", &[(5, 5), (5, 4), (5, 3)]),
+            ],
+            hazards: vec![],
+        };
+        assert!(search(&board, &game_info(0), SearchMode::Alternating, &budget(0)).is_err());
+    }
+
+    #[test]
+    fn test_forced_off_the_board() {
+        // Lines where we are trapped on the bottom row force us Down off the
+        // board, hashing those positions must not end the search
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 0), (1, 1), (1, 2)]),
+                snake("them", &[(0, 2), (0, 3), (0, 4), (0, 5), (0, 6)]),
+            ],
+            hazards: vec![],
+        };
+        for mode in [SearchMode::Alternating, SearchMode::Simultaneous] {
+            let result = search_threads(&board, &game_info(100), mode, &budget(100), 1).unwrap();
+            assert_eq!(result.moves[0].mv, Movement::Right, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_too_large_to_hash() {
+        // Cells past 32x32 would share Zobrist keys, the caller falls back instead
+        let board = Board {
+            height: 33,
+            width: 33,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (1, 1)]),
+                snake("them", &[(20, 20), (20, 21), (20, 22)]),
+            ],
+            hazards: vec![],
+        };
+        let error = search(&board, &game_info(100), SearchMode::Alternating, &budget(100)).unwrap_err();
+        assert!(error.contains("33x33"), "{}", error);
+    }
+
+    #[test]
+    fn test_principal_variation() {
+        let board = midgame_board();
+        let simple_board = SimpleBoard::from(&board, &game_info(100));
+        for mode in [SearchMode::Alternating, SearchMode::Simultaneous] {
+            let result = search_threads(&board, &game_info(100), mode, &budget(100), 1).unwrap();
+            assert!(result.depth > 0, "{:?}", mode);
+            assert!(result.nodes > 0, "{:?}", mode);
+            assert!(!result.pv.is_empty(), "{:?}", mode);
+            let plies = match mode {
+                SearchMode::Alternating => result.depth * 2,
+                SearchMode::Simultaneous => result.depth,
+            };
+            assert!(result.pv.len() <= plies as usize, "{:?}", mode);
+
+            // The line starts with the chosen move and every board follows from the one before
+            let (first, _) = &result.pv[0];
+            assert_eq!(move_key(&first[..result.moves.len()]), move_key(&result.moves));
+            let mut previous = simple_board.clone();
+            for (ply, (moves, board)) in result.pv.iter().enumerate() {
+                let expected = match mode {
+                    SearchMode::Alternating => previous
+                        .simulate_move(ply % 2 == 0)
+                        .into_iter()
+                        .find(|(m, _)| move_key(m) == move_key(moves))
+                        .unwrap()
+                        .1,
+                    SearchMode::Simultaneous => previous.simulate_joint(moves),
+                };
+                assert_eq!(board.zobrist_hash(true), expected.zobrist_hash(true), "{:?}", mode);
+                previous = board.clone();
+            }
+        }
+    }
+
+    // Iterative deepening to a fixed number of turns, returns our move and the nodes searched
+    fn fixed_depth(board: &Board, ordering: MoveOrdering, turns: i32) -> (Movement, u64) {
+        let simple_board = SimpleBoard::from(board, &game_info(0));
+        let mut simulations = simple_board.simulate_move(true);
+        let mut ctx = SearchContext::new(budget(600_000), 16, SearchMode::Alternating, ordering);
+        for t in 1..=turns {
+            let (idx, _, _) = search_root(&simulations, t * 2, &mut ctx).unwrap();
+            let best = simulations.remove(idx);
+            simulations.insert(0, best);
+        }
+        let ours = simulations[0].0.iter().find(|m| m.id == 0).unwrap().mv;
+        (ours, ctx.nodes)
+    }
+
+    fn midgame_board() -> Board {
+        Board {
+            height: 11,
+            width: 11,
+            food: vec![Coord { x: 5, y: 5 }, Coord { x: 9, y: 1 }, Coord { x: 1, y: 8 }],
+            snakes: vec![
+                snake("us", &[(3, 3), (3, 2), (2, 2), (1, 2), (1, 1)]),
+                snake("them", &[(7, 6), (7, 7), (8, 7), (8, 8), (8, 9), (7, 9)]),
+            ],
+            hazards: vec![],
+        }
+    }
+
+    #[test]
+    fn test_move_key() {
+        let a = [SnakeMove { id: 1, mv: Movement::Up }, SnakeMove { id: 2, mv: Movement::Left }];
+        let b = [SnakeMove { id: 1, mv: Movement::Left }, SnakeMove { id: 2, mv: Movement::Up }];
+        assert_eq!(move_key(&a), move_key(&a.clone()));
+        assert_ne!(move_key(&a), move_key(&b));
+        assert_ne!(move_key(&a[..1]), move_key(&[]));
+
+        // Royale and squad games have more snakes than a key used to hold
+        let all: Vec<SnakeMove> = (0..MAX_SNAKES).map(|id| SnakeMove { id, mv: Movement::Right }).collect();
+        let mut last = all.clone();
+        last[MAX_SNAKES - 1].mv = Movement::Left;
+        assert_ne!(move_key(&all), move_key(&last));
+        let first = [SnakeMove { id: 0, mv: Movement::Up }];
+        let ninth = [SnakeMove { id: 8, mv: Movement::Up }];
+        assert_ne!(move_key(&first), move_key(&ninth));
+    }
+
+    #[test]
+    fn test_orderings_agree() {
+        let board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![
+                snake("us", &[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]),
+                snake(
+                    "them",
+                    &[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)],
+                ),
+            ],
+            hazards: vec![],
+        };
+        assert_eq!(fixed_depth(&board, MoveOrdering::Heuristic, 3).0, Movement::Right);
+        assert_eq!(fixed_depth(&board, MoveOrdering::Killers, 3).0, Movement::Right);
+    }
+
+    // cargo test --release bench_move_ordering -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_move_ordering() {
+        let board = midgame_board();
+        for turns in 1..=7 {
+            let (heuristic_move, heuristic_nodes) = fixed_depth(&board, MoveOrdering::Heuristic, turns);
+            let (killers_move, killers_nodes) = fixed_depth(&board, MoveOrdering::Killers, turns);
+            println!(
+                "{} turns: heuristic {} nodes ({}), killers {} nodes ({})",
+                turns, heuristic_nodes, heuristic_move, killers_nodes, killers_move
+            );
+        }
     }
-    let depth_diff = best_value.1 - depth;
-    ((best_value.0 * depth_diff + board.heuristic(true)) / (depth_diff+1), best_value.1)
 }
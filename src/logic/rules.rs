@@ -0,0 +1,761 @@
+// Implementation of the official Battlesnake rules, see
+// https://github.com/BattlesnakeOfficial/rules/blob/main/standard.go
+//
+// A turn is resolved in the same order as the official engine:
+// move snakes, reduce health, damage hazards, feed snakes, eliminate snakes
+// and finally spawn food on the board without the eliminated snakes. All
+// snakes move simultaneously. In constrictor games all food is removed and
+// every snake grows and is fed after the eliminations, no food is spawned.
+
+use crate::logic::simple::Movement;
+use crate::{Battlesnake, Board, Coord, GameMode, Ruleset, Topology};
+use rand::Rng;
+use std::collections::HashMap;
+
+pub const SNAKE_MAX_HEALTH: i32 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub food_spawn_chance: u32,
+    pub minimum_food: u32,
+    pub hazard_damage_per_turn: i32,
+    pub topology: Topology,
+    pub mode: GameMode,
+}
+
+impl Settings {
+    pub fn from(ruleset: &Ruleset) -> Self {
+        Settings {
+            food_spawn_chance: ruleset.settings.food_spawn_chance,
+            minimum_food: ruleset.settings.minimum_food,
+            hazard_damage_per_turn: ruleset.settings.hazard_damage_per_turn,
+            topology: ruleset.topology(),
+            mode: ruleset.mode(),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::from(&Ruleset::default())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EliminationCause {
+    OutOfHealth,
+    OutOfBounds,
+    SelfCollision,
+    Collision,
+    HeadToHead,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Elimination {
+    pub id: String,
+    pub cause: EliminationCause,
+    pub by: Option<String>,
+}
+
+// Steps the board one turn forward. Snakes without a move keep going in the
+// direction they were heading. Eliminated snakes are removed from the board
+// and returned.
+pub fn step<R: Rng>(
+    board: &mut Board,
+    moves: &HashMap<String, Movement>,
+    settings: &Settings,
+    rng: &mut R,
+) -> Vec<Elimination> {
+    let mut eliminations = Vec::new();
+    move_snakes(board, moves, settings);
+    reduce_health(board);
+    damage_hazards(board, settings, &mut eliminations);
+    feed_snakes(board, &eliminations);
+    eliminate_snakes(board, &mut eliminations);
+    if settings.mode == GameMode::Constrictor {
+        grow_snakes_constrictor(board);
+    }
+    board
+        .snakes
+        .retain(|s| !eliminations.iter().any(|e| e.id == s.id));
+    spawn_food(board, settings, rng);
+    eliminations
+}
+
+pub fn move_snakes(board: &mut Board, moves: &HashMap<String, Movement>, settings: &Settings) {
+    let size = (board.width, board.height);
+    for snake in board.snakes.iter_mut() {
+        let movement = match moves.get(&snake.id) {
+            Some(&m) if m != Movement::None => m,
+            _ => default_move(snake, size, settings),
+        };
+        let new_head = moved_head(&snake.body[0], movement, size, settings);
+        snake.body.pop();
+        snake.body.insert(0, new_head);
+        snake.head = new_head;
+    }
+}
+
+pub fn reduce_health(board: &mut Board) {
+    for snake in board.snakes.iter_mut() {
+        snake.health -= 1;
+    }
+}
+
+pub fn damage_hazards(board: &mut Board, settings: &Settings, eliminations: &mut Vec<Elimination>) {
+    for snake in board.snakes.iter_mut() {
+        if is_eliminated(eliminations, snake) {
+            continue;
+        }
+        let head = snake.body[0];
+        // Food protects from hazard damage on the turn it is eaten
+        if board.food.contains(&head) {
+            continue;
+        }
+        // Stacked hazards deal damage once per hazard
+        let stacked = board.hazards.iter().filter(|&&h| h == head).count() as i32;
+        if stacked == 0 {
+            continue;
+        }
+        snake.health = (snake.health - stacked * settings.hazard_damage_per_turn).max(0);
+        // The official rules count this as running out of health
+        if snake.health <= 0 {
+            eliminations.push(Elimination {
+                id: snake.id.clone(),
+                cause: EliminationCause::OutOfHealth,
+                by: None,
+            });
+        }
+    }
+}
+
+pub fn feed_snakes(board: &mut Board, eliminations: &[Elimination]) {
+    let mut remaining_food = Vec::new();
+    for food in board.food.iter() {
+        let mut eaten = false;
+        for snake in board.snakes.iter_mut() {
+            if is_eliminated(eliminations, snake) || snake.body[0] != *food {
+                continue;
+            }
+            let tail = *snake.body.last().unwrap();
+            snake.body.push(tail);
+            snake.length = snake.body.len() as i32;
+            snake.health = SNAKE_MAX_HEALTH;
+            eaten = true;
+        }
+        if !eaten {
+            remaining_food.push(*food);
+        }
+    }
+    board.food = remaining_food;
+}
+
+pub fn spawn_food<R: Rng>(board: &mut Board, settings: &Settings, rng: &mut R) {
+    if settings.mode == GameMode::Constrictor {
+        return;
+    }
+    let current = board.food.len() as u32;
+    let count = if current < settings.minimum_food {
+        settings.minimum_food - current
+    } else if settings.food_spawn_chance > 0
+        && 100 - rng.gen_range(0..100) < settings.food_spawn_chance
+    {
+        1
+    } else {
+        0
+    };
+    for _ in 0..count {
+        let free = unoccupied_points(board, settings);
+        if free.is_empty() {
+            return;
+        }
+        let food = free[rng.gen_range(0..free.len())];
+        board.food.push(food);
+    }
+}
+
+pub fn eliminate_snakes(board: &Board, eliminations: &mut Vec<Elimination>) {
+    for snake in board.snakes.iter() {
+        if is_eliminated(eliminations, snake) {
+            continue;
+        }
+        let cause = if snake.health <= 0 {
+            EliminationCause::OutOfHealth
+        } else if out_of_bounds(board, &snake.body[0]) {
+            EliminationCause::OutOfBounds
+        } else {
+            continue;
+        };
+        eliminations.push(Elimination {
+            id: snake.id.clone(),
+            cause,
+            by: None,
+        });
+    }
+
+    // Collisions are resolved simultaneously among the remaining snakes
+    let alive: Vec<&Battlesnake> = board
+        .snakes
+        .iter()
+        .filter(|s| !is_eliminated(eliminations, s))
+        .collect();
+    let mut collisions = Vec::new();
+    for snake in alive.iter() {
+        if body_collided(snake, snake) {
+            collisions.push(Elimination {
+                id: snake.id.clone(),
+                cause: EliminationCause::SelfCollision,
+                by: Some(snake.id.clone()),
+            });
+            continue;
+        }
+        if let Some(other) = alive
+            .iter()
+            .find(|o| o.id != snake.id && body_collided(snake, o))
+        {
+            collisions.push(Elimination {
+                id: snake.id.clone(),
+                cause: EliminationCause::Collision,
+                by: Some(other.id.clone()),
+            });
+            continue;
+        }
+        if let Some(other) = alive
+            .iter()
+            .find(|o| o.id != snake.id && lost_head_to_head(snake, o))
+        {
+            collisions.push(Elimination {
+                id: snake.id.clone(),
+                cause: EliminationCause::HeadToHead,
+                by: Some(other.id.clone()),
+            });
+        }
+    }
+    eliminations.extend(collisions);
+}
+
+pub fn grow_snakes_constrictor(board: &mut Board) {
+    board.food.clear();
+    for snake in board.snakes.iter_mut() {
+        snake.health = SNAKE_MAX_HEALTH;
+        let len = snake.body.len();
+        if len < 2 || snake.body[len - 1] != snake.body[len - 2] {
+            let tail = snake.body[len - 1];
+            snake.body.push(tail);
+            snake.length = snake.body.len() as i32;
+        }
+    }
+}
+
+fn is_eliminated(eliminations: &[Elimination], snake: &Battlesnake) -> bool {
+    eliminations.iter().any(|e| e.id == snake.id)
+}
+
+fn body_collided(snake: &Battlesnake, other: &Battlesnake) -> bool {
+    other.body.iter().skip(1).any(|c| c == &snake.body[0])
+}
+
+fn lost_head_to_head(snake: &Battlesnake, other: &Battlesnake) -> bool {
+    snake.body[0] == other.body[0] && snake.body.len() <= other.body.len()
+}
+
+fn out_of_bounds(board: &Board, coord: &Coord) -> bool {
+    coord.x < 0 || coord.x >= board.width || coord.y < 0 || coord.y >= board.height
+}
+
+fn next_head(head: &Coord, movement: Movement) -> Coord {
+    match movement {
+        Movement::Up => Coord { x: head.x, y: head.y + 1 },
+        Movement::Down => Coord { x: head.x, y: head.y - 1 },
+        Movement::Left => Coord { x: head.x - 1, y: head.y },
+        Movement::Right => Coord { x: head.x + 1, y: head.y },
+        Movement::None => *head,
+    }
+}
+
+// The cell a head moves into, on wrapped boards it comes back in on the other side
+fn moved_head(
+    head: &Coord,
+    movement: Movement,
+    (width, height): (i32, i32),
+    settings: &Settings,
+) -> Coord {
+    let mut new_head = next_head(head, movement);
+    if settings.topology == Topology::Wrapped {
+        new_head.x = new_head.x.rem_euclid(width);
+        new_head.y = new_head.y.rem_euclid(height);
+    }
+    new_head
+}
+
+// Keep going in the direction from the neck to the head, up if there is none
+fn default_move(snake: &Battlesnake, size: (i32, i32), settings: &Settings) -> Movement {
+    if snake.body.len() < 2 {
+        return Movement::Up;
+    }
+    let (head, neck) = (snake.body[0], snake.body[1]);
+    Movement::all()
+        .into_iter()
+        .find(|&m| moved_head(&neck, m, size, settings) == head)
+        .unwrap_or(Movement::Up)
+}
+
+// Cells free of snakes, food and hazards that no snake can move into next turn
+fn unoccupied_points(board: &Board, settings: &Settings) -> Vec<Coord> {
+    let size = (board.width, board.height);
+    let mut points = Vec::new();
+    for y in 0..board.height {
+        for x in 0..board.width {
+            let coord = Coord { x, y };
+            let occupied = board.food.contains(&coord)
+                || board.hazards.contains(&coord)
+                || board.snakes.iter().any(|s| {
+                    s.body.contains(&coord)
+                        || Movement::all()
+                            .into_iter()
+                            .any(|m| moved_head(&s.body[0], m, size, settings) == coord)
+                });
+            if !occupied {
+                points.push(coord);
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{step, Elimination, EliminationCause, Settings, SNAKE_MAX_HEALTH};
+    use crate::{GameMode, Topology};
+    use crate::logic::simple::Movement;
+    use crate::{Battlesnake, Board, Coord};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::collections::HashMap;
+
+    fn snake(id: &str, body: &[(i32, i32)], health: i32) -> Battlesnake {
+        let body: Vec<Coord> = body.iter().map(|&(x, y)| Coord { x, y }).collect();
+        Battlesnake {
+            id: id.to_string(),
+            name: id.to_string(),
+            health,
+            head: body[0],
+            length: body.len() as i32,
+            body,
+            latency: String::from("0"),
+            shout: None,
+            squad: String::new(),
+            customizations: Default::default(),
+        }
+    }
+
+    fn coords(points: &[(i32, i32)]) -> Vec<Coord> {
+        points.iter().map(|&(x, y)| Coord { x, y }).collect()
+    }
+
+    // id, body and health of a surviving snake
+    type ExpectedSnake = (&'static str, Vec<(i32, i32)>, i32);
+
+    struct Fixture {
+        name: &'static str,
+        snakes: Vec<Battlesnake>,
+        food: Vec<(i32, i32)>,
+        hazards: Vec<(i32, i32)>,
+        moves: Vec<(&'static str, Movement)>,
+        expected_snakes: Vec<ExpectedSnake>,
+        expected_food: Vec<(i32, i32)>,
+        expected_eliminations: Vec<(&'static str, EliminationCause, Option<&'static str>)>,
+    }
+
+    // No random food so the outcome only depends on the rules
+    const NO_SPAWN: Settings = Settings {
+        food_spawn_chance: 0,
+        minimum_food: 0,
+        hazard_damage_per_turn: 14,
+        topology: Topology::Bounded,
+        mode: GameMode::Standard,
+    };
+
+    fn fixtures() -> Vec<Fixture> {
+        vec![
+            Fixture {
+                name: "move and reduce health",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 50)],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![("a", vec![(1, 2), (1, 1), (1, 0)], 49)],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "eating resets health and stacks the tail",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 50)],
+                food: vec![(1, 2), (5, 5)],
+                hazards: vec![],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![("a", vec![(1, 2), (1, 1), (1, 0), (1, 0)], SNAKE_MAX_HEALTH)],
+                expected_food: vec![(5, 5)],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "missing move continues straight",
+                snakes: vec![snake("a", &[(3, 3), (2, 3), (1, 3)], 50)],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![],
+                expected_snakes: vec![("a", vec![(4, 3), (3, 3), (2, 3)], 49)],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "starvation",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 1)],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![("a", EliminationCause::OutOfHealth, None)],
+            },
+            Fixture {
+                name: "eating on the last point of health survives",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 1)],
+                food: vec![(1, 2)],
+                hazards: vec![],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![("a", vec![(1, 2), (1, 1), (1, 0), (1, 0)], SNAKE_MAX_HEALTH)],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "out of bounds",
+                snakes: vec![snake("a", &[(0, 5), (1, 5), (2, 5)], 50)],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Left)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![("a", EliminationCause::OutOfBounds, None)],
+            },
+            Fixture {
+                name: "following a tail is safe",
+                snakes: vec![
+                    snake("a", &[(2, 2), (2, 1), (1, 1)], 50),
+                    snake("b", &[(4, 1), (4, 2), (3, 2)], 50),
+                ],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Right), ("b", Movement::Down)],
+                expected_snakes: vec![
+                    ("a", vec![(3, 2), (2, 2), (2, 1)], 49),
+                    ("b", vec![(4, 0), (4, 1), (4, 2)], 49),
+                ],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "stacked tail is not safe",
+                snakes: vec![
+                    snake("a", &[(2, 2), (2, 1), (1, 1)], 50),
+                    snake("b", &[(5, 5), (5, 4), (3, 2), (3, 2)], 50),
+                ],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Right), ("b", Movement::Up)],
+                expected_snakes: vec![("b", vec![(5, 6), (5, 5), (5, 4), (3, 2)], 49)],
+                expected_food: vec![],
+                expected_eliminations: vec![("a", EliminationCause::Collision, Some("b"))],
+            },
+            Fixture {
+                name: "self collision",
+                snakes: vec![snake("a", &[(1, 1), (1, 2), (0, 2), (0, 1), (0, 0), (1, 0)], 50)],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Left)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![("a", EliminationCause::SelfCollision, Some("a"))],
+            },
+            Fixture {
+                name: "head to head, longer snake wins",
+                snakes: vec![
+                    snake("a", &[(4, 5), (3, 5), (2, 5), (1, 5)], 50),
+                    snake("b", &[(6, 5), (7, 5), (8, 5)], 50),
+                ],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Right), ("b", Movement::Left)],
+                expected_snakes: vec![("a", vec![(5, 5), (4, 5), (3, 5), (2, 5)], 49)],
+                expected_food: vec![],
+                expected_eliminations: vec![("b", EliminationCause::HeadToHead, Some("a"))],
+            },
+            Fixture {
+                name: "head to head, equal length both die",
+                snakes: vec![
+                    snake("a", &[(4, 5), (3, 5), (2, 5)], 50),
+                    snake("b", &[(6, 5), (7, 5), (8, 5)], 50),
+                ],
+                food: vec![],
+                hazards: vec![],
+                moves: vec![("a", Movement::Right), ("b", Movement::Left)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![
+                    ("a", EliminationCause::HeadToHead, Some("b")),
+                    ("b", EliminationCause::HeadToHead, Some("a")),
+                ],
+            },
+            Fixture {
+                name: "head to head on food counts the growth",
+                snakes: vec![
+                    snake("a", &[(4, 5), (3, 5), (2, 5)], 50),
+                    snake("b", &[(6, 5), (7, 5), (8, 5)], 50),
+                ],
+                food: vec![(5, 5)],
+                hazards: vec![],
+                moves: vec![("a", Movement::Right), ("b", Movement::Left)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![
+                    ("a", EliminationCause::HeadToHead, Some("b")),
+                    ("b", EliminationCause::HeadToHead, Some("a")),
+                ],
+            },
+            Fixture {
+                name: "stacked hazards",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 50)],
+                food: vec![],
+                hazards: vec![(1, 2), (1, 2)],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![("a", vec![(1, 2), (1, 1), (1, 0)], 49 - 2 * 14)],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "food in hazard protects",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 10)],
+                food: vec![(1, 2)],
+                hazards: vec![(1, 2)],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![("a", vec![(1, 2), (1, 1), (1, 0), (1, 0)], SNAKE_MAX_HEALTH)],
+                expected_food: vec![],
+                expected_eliminations: vec![],
+            },
+            Fixture {
+                name: "hazard elimination",
+                snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 10)],
+                food: vec![],
+                hazards: vec![(1, 2)],
+                moves: vec![("a", Movement::Up)],
+                expected_snakes: vec![],
+                expected_food: vec![],
+                expected_eliminations: vec![("a", EliminationCause::OutOfHealth, None)],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for fixture in fixtures() {
+            let mut board = Board {
+                height: 11,
+                width: 11,
+                food: coords(&fixture.food),
+                snakes: fixture.snakes.clone(),
+                hazards: coords(&fixture.hazards),
+            };
+            let moves: HashMap<String, Movement> = fixture
+                .moves
+                .iter()
+                .map(|&(id, m)| (id.to_string(), m))
+                .collect();
+
+            let eliminations = step(&mut board, &moves, &NO_SPAWN, &mut rng);
+
+            let expected: Vec<Elimination> = fixture
+                .expected_eliminations
+                .iter()
+                .map(|&(id, cause, by)| Elimination {
+                    id: id.to_string(),
+                    cause,
+                    by: by.map(String::from),
+                })
+                .collect();
+            assert_eq!(eliminations, expected, "{}", fixture.name);
+            assert_eq!(board.food, coords(&fixture.expected_food), "{}", fixture.name);
+            assert_eq!(board.snakes.len(), fixture.expected_snakes.len(), "{}", fixture.name);
+            for (id, body, health) in fixture.expected_snakes.iter() {
+                let snake = board.snakes.iter().find(|s| &s.id == id).unwrap();
+                assert_eq!(snake.body, coords(body), "{}", fixture.name);
+                assert_eq!(snake.head, snake.body[0], "{}", fixture.name);
+                assert_eq!(snake.health, *health, "{}", fixture.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_spawn_minimum_food() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut board = Board {
+            height: 7,
+            width: 7,
+            food: vec![],
+            snakes: vec![snake("a", &[(3, 3), (3, 2), (3, 1)], 50)],
+            hazards: vec![],
+        };
+        let settings = Settings {
+            food_spawn_chance: 0,
+            minimum_food: 3,
+            hazard_damage_per_turn: 0,
+            topology: Topology::Bounded,
+            mode: GameMode::Standard,
+        };
+        step(&mut board, &HashMap::new(), &settings, &mut rng);
+
+        assert_eq!(board.food.len(), 3);
+        let snake = &board.snakes[0];
+        for food in board.food.iter() {
+            assert!(!snake.body.contains(food));
+            assert!((food.x - snake.body[0].x).abs() + (food.y - snake.body[0].y).abs() > 1);
+        }
+    }
+
+    #[test]
+    fn test_spawn_chance() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut board = Board {
+            height: 11,
+            width: 11,
+            food: vec![Coord { x: 0, y: 0 }],
+            snakes: vec![],
+            hazards: vec![],
+        };
+        let always = Settings {
+            food_spawn_chance: 100,
+            minimum_food: 1,
+            hazard_damage_per_turn: 0,
+            topology: Topology::Bounded,
+            mode: GameMode::Standard,
+        };
+        step(&mut board, &HashMap::new(), &always, &mut rng);
+        assert_eq!(board.food.len(), 2);
+
+        step(&mut board, &HashMap::new(), &NO_SPAWN, &mut rng);
+        assert_eq!(board.food.len(), 2);
+    }
+
+    #[test]
+    fn test_food_spawns_after_eliminations() {
+        // The starving snake fills the whole board, only its elimination
+        // leaves room for food
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut board = Board {
+            height: 1,
+            width: 2,
+            food: vec![],
+            snakes: vec![snake("a", &[(0, 0), (0, 0)], 1)],
+            hazards: vec![],
+        };
+        let moves: HashMap<String, Movement> =
+            vec![(String::from("a"), Movement::Right)].into_iter().collect();
+        let settings = Settings {
+            minimum_food: 1,
+            ..NO_SPAWN
+        };
+
+        let eliminations = step(&mut board, &moves, &settings, &mut rng);
+        assert_eq!(eliminations[0].cause, EliminationCause::OutOfHealth);
+        assert!(board.snakes.is_empty());
+        assert_eq!(board.food.len(), 1);
+    }
+
+    #[test]
+    fn test_wrapped_moves() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut board = Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![
+                snake("a", &[(0, 5), (1, 5), (2, 5)], 50),
+                snake("b", &[(5, 10), (5, 9), (5, 8)], 50),
+            ],
+            hazards: vec![],
+        };
+        let moves: HashMap<String, Movement> = vec![
+            (String::from("a"), Movement::Left),
+            (String::from("b"), Movement::Up),
+        ]
+        .into_iter()
+        .collect();
+        let wrapped = Settings {
+            topology: Topology::Wrapped,
+            ..NO_SPAWN
+        };
+
+        let eliminations = step(&mut board, &moves, &wrapped, &mut rng);
+        assert!(eliminations.is_empty());
+        assert_eq!(board.snakes[0].head, Coord { x: 10, y: 5 });
+        assert_eq!(board.snakes[1].head, Coord { x: 5, y: 0 });
+
+        // Without a move both keep going the way they came in across the edge
+        step(&mut board, &HashMap::new(), &wrapped, &mut rng);
+        assert_eq!(board.snakes[0].head, Coord { x: 9, y: 5 });
+        assert_eq!(board.snakes[1].head, Coord { x: 5, y: 1 });
+    }
+
+    #[test]
+    fn test_wrapped_food_spawn() {
+        // The head moves to (0,0), across the left edge (3,0) is next to it
+        // and only (2,0) is left for food
+        for seed in 0..10 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut board = Board {
+                height: 1,
+                width: 4,
+                food: vec![],
+                snakes: vec![snake("a", &[(1, 0), (2, 0)], 50)],
+                hazards: vec![],
+            };
+            let moves: HashMap<String, Movement> =
+                vec![(String::from("a"), Movement::Left)].into_iter().collect();
+            let wrapped = Settings {
+                minimum_food: 1,
+                topology: Topology::Wrapped,
+                ..NO_SPAWN
+            };
+
+            step(&mut board, &moves, &wrapped, &mut rng);
+            assert_eq!(board.food, coords(&[(2, 0)]), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn test_constrictor() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut board = Board {
+            height: 11,
+            width: 11,
+            food: vec![Coord { x: 8, y: 8 }],
+            snakes: vec![snake("a", &[(1, 1), (1, 0), (0, 0)], 50)],
+            hazards: vec![],
+        };
+        let constrictor = Settings {
+            minimum_food: 1,
+            mode: GameMode::Constrictor,
+            ..NO_SPAWN
+        };
+        let moves: HashMap<String, Movement> =
+            vec![(String::from("a"), Movement::Up)].into_iter().collect();
+
+        step(&mut board, &moves, &constrictor, &mut rng);
+        assert!(board.food.is_empty());
+        assert_eq!(board.snakes[0].health, SNAKE_MAX_HEALTH);
+        assert_eq!(board.snakes[0].body, coords(&[(1, 2), (1, 1), (1, 0), (1, 0)]));
+
+        step(&mut board, &moves, &constrictor, &mut rng);
+        assert_eq!(board.snakes[0].body, coords(&[(1, 3), (1, 2), (1, 1), (1, 0), (1, 0)]));
+    }
+}
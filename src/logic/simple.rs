@@ -1,11 +1,15 @@
 //use rocket::futures::future::ok;
 
 //use crate::logic::{collision_with_body, collision_with_snakes, get_safe_moves, out_of_bounds};
-use crate::{Battlesnake, Board, Coord, GameInfo};
+use crate::logic::rules::SNAKE_MAX_HEALTH;
+use crate::logic::weights::HeuristicWeights;
+use crate::logic::zobrist;
+use crate::{Battlesnake, Board, Coord, GameInfo, GameMode, Ruleset, Topology};
 use log::info;
 use serde::{Serialize, Serializer};
 use std::cell::Cell;
-use std::collections::{HashMap, VecDeque};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::{Debug, Display};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -37,7 +41,7 @@ impl PartialOrd for Movement {
 }
 
 impl Movement {
-    fn to_order(&self) -> u8 {
+    fn to_order(self) -> u8 {
         match self {
             Movement::Up => 0,
             Movement::Down => 1,
@@ -87,59 +91,113 @@ impl Debug for SnakeMove {
     }
 }
 
+// The parts of the ruleset the simulation needs, cheap to copy with every board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimpleRules {
+    pub mode: GameMode,
+    pub topology: Topology,
+    pub hazard_damage: i32,
+    pub allow_body_collisions: bool,
+    pub shared_elimination: bool,
+    pub shared_health: bool,
+    pub shared_length: bool,
+}
+
+impl SimpleRules {
+    pub fn from(ruleset: &Ruleset) -> Self {
+        let mode = ruleset.mode();
+        let squad = &ruleset.settings.squad;
+        let is_squad = mode == GameMode::Squad;
+        SimpleRules {
+            mode,
+            topology: ruleset.topology(),
+            hazard_damage: ruleset.settings.hazard_damage_per_turn,
+            allow_body_collisions: is_squad && squad.allow_body_collisions,
+            shared_elimination: is_squad && squad.shared_elimination,
+            shared_health: is_squad && squad.shared_health,
+            shared_length: is_squad && squad.shared_length,
+        }
+    }
+}
+
+impl Default for SimpleRules {
+    fn default() -> Self {
+        SimpleRules::from(&Ruleset::default())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct SimpleBoard {
+    pub width: i32,
+    pub height: i32,
     pub food: Vec<Coord>,
+    // Hazards can be stacked, each copy of a cell deals damage
+    pub hazards: Vec<Coord>,
     pub snakes: Vec<Option<SimpleSnake>>,
-    team: [usize; 2],
-    opps: [usize; 2],
+    // Snake indices grouped by team, teams[0] is always our team
+    teams: Vec<Vec<usize>>,
+    pub rules: SimpleRules,
+    pub weights: HeuristicWeights,
     pub stored_fast_heuristic: Cell<Option<i32>>,
     pub stored_flood_fill_heuristic: Cell<Option<i32>>,
 }
 impl SimpleBoard {
     pub fn from(board: &Board, game_info: &GameInfo) -> Self {
         let mut simple_board = SimpleBoard {
+            width: board.width,
+            height: board.height,
             food: board.food.clone(),
+            hazards: board.hazards.clone(),
             snakes: Vec::new(),
-            team: [10; 2],
-            opps: [10; 2],
+            teams: vec![Vec::new()],
+            rules: SimpleRules::from(&game_info.ruleset),
+            weights: game_info.weights,
             stored_fast_heuristic: Cell::new(None),
             stored_flood_fill_heuristic: Cell::new(None),
         };
-        let mut friendly_count = 0;
-        let mut enemy_count = 0;
+        // Team of every opponent squad seen so far
+        let mut squads: HashMap<&str, usize> = HashMap::new();
         for (idx, snake) in board.snakes.iter().enumerate() {
             simple_board.snakes.push(Some(SimpleSnake::from(snake)));
             if game_info.agent_ids.contains(&snake.id) {
-                simple_board.team[friendly_count] = idx;
-                friendly_count += 1;
+                simple_board.teams[0].push(idx);
+            } else if let Some(&team) = squads.get(snake.squad.as_str()) {
+                simple_board.teams[team].push(idx);
             } else {
-                simple_board.opps[enemy_count] = idx;
-                enemy_count += 1;
+                // Opponents without a squad play alone
+                if !snake.squad.is_empty() {
+                    squads.insert(&snake.squad, simple_board.teams.len());
+                }
+                simple_board.teams.push(vec![idx]);
             }
         }
-        while friendly_count < 2 {
-            simple_board.team[friendly_count] = simple_board.snakes.len();
-            simple_board.snakes.push(None);
-            friendly_count += 1;
-        }
-        while enemy_count < 2 {
-            simple_board.opps[enemy_count] = simple_board.snakes.len();
-            simple_board.snakes.push(None);
-            enemy_count += 1;
-        }
         simple_board
     }
 
-    // fn evaluate_team(&self, our_team: bool) -> usize {
-    //     let mut v = 0;
-    //     for snake in self.snakes.iter() {
-    //         if snake.our_team == our_team {
-    //             v += snake.evaluate_value();
-    //         }
-    //     }
-    //     v
-    // }
+    fn team(&self) -> &[usize] {
+        &self.teams[0]
+    }
+
+    fn opps(&self) -> Vec<usize> {
+        self.teams.iter().skip(1).flatten().copied().collect()
+    }
+
+    fn side(&self, our_team: bool) -> Vec<usize> {
+        if our_team {
+            self.team().to_vec()
+        } else {
+            self.opps()
+        }
+    }
+
+    fn same_team(&self, a: usize, b: usize) -> bool {
+        self.teams.iter().any(|t| t.contains(&a) && t.contains(&b))
+    }
+
+    // Squad members may move through each other without dying
+    fn can_pass_through(&self, a: usize, b: usize) -> bool {
+        a != b && self.rules.allow_body_collisions && self.same_team(a, b)
+    }
 
     pub fn heuristic(&self, fast: bool) -> i32 {
         let fast_heuristic: i32;
@@ -152,174 +210,294 @@ impl SimpleBoard {
         if fast || fast_heuristic == i32::MIN || fast_heuristic == i32::MAX {
             return fast_heuristic;
         }
-        
+
         let flood_fill_heuristic: i32;
         if let Some(v) = self.stored_flood_fill_heuristic.get() {
             flood_fill_heuristic = v;
         } else {
-            flood_fill_heuristic = self.flood_fill().len() as i32;
+            flood_fill_heuristic = self.flood_fill_heuristic();
         }
-        
-        return fast_heuristic + flood_fill_heuristic;
+
+        fast_heuristic + flood_fill_heuristic
     }
 
     fn fast_heuristic(&self) -> i32 {
-        if self.snakes.len() == 0 {
+        if self.snakes.is_empty() {
             self.stored_fast_heuristic.set(Some(0));
             return 0;
         }
+        let our_value = match self.team_fast_heuristic(self.team()) {
+            Some(v) => v,
+            None => {
+                self.stored_fast_heuristic.set(Some(i32::MIN));
+                info!("all snakes in our team dead");
+                return i32::MIN;
+            }
+        };
+        let opp_values: Vec<Option<i32>> = self
+            .teams
+            .iter()
+            .skip(1)
+            .map(|t| self.team_fast_heuristic(t))
+            .collect();
+        // Solo games have no opponents to win against
+        if !opp_values.is_empty() && opp_values.iter().all(|v| v.is_none()) {
+            self.stored_fast_heuristic.set(Some(i32::MAX));
+            return i32::MAX;
+        }
+        let v = our_value - opp_values.iter().flatten().sum::<i32>();
+        self.stored_fast_heuristic.set(Some(v));
+        v
+    }
+
+    // Value of a single team, None if every snake in it is dead
+    fn team_fast_heuristic(&self, team: &[usize]) -> Option<i32> {
+        let w = &self.weights;
         let mut health_value: i32 = 0;
+        let mut hazard_value: i32 = 0;
         let mut length_value: i32 = 0;
-        let mut death_value: i32 = 0;
-        let mut dead_snake_count = 0;
+        let mut alive_value: i32 = 0;
         // lägg in så man är 1 längre än motståndare
-        for f_idx in self.team {
-            if f_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                dead_snake_count += 1;
-                death_value -= 1;
-            } else {
-                match &self.snakes[f_idx] {
-                    Some(snake) => {
-                        length_value += snake.body.len() as i32;
-                        if snake.health < 20 {
-                            health_value -= 20 - snake.health;
-                        }
-                    }
-                    None => {
-                        //info!("Dead snake in our team");
-                        dead_snake_count += 1;
-                        death_value -= 1;
-                    }
-                }
-            }
-        }
-        if dead_snake_count == 2 {
-            self.stored_fast_heuristic.set(Some(i32::MIN));
-            info!("both snakes dead");
-            return i32::MIN;
-        }
-        dead_snake_count = 0;
-        for e_idx in self.opps {
-            if e_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                dead_snake_count += 1;
-                death_value += 1;
-            } else {
-                match &self.snakes[e_idx] {
-                    Some(snake) => {
-                        length_value -= snake.body.len() as i32;
-                        if snake.health < 20 {
-                            health_value += 20 - snake.health;
-                        }
-                    }
-                    None => {
-                        dead_snake_count += 1;
-                        death_value += 1;
-                    }
+        for &idx in team {
+            if let Some(Some(snake)) = self.snakes.get(idx) {
+                alive_value += 1;
+                length_value += snake.body.len() as i32;
+                if snake.health < w.health_threshold {
+                    health_value -= w.health_threshold - snake.health;
                 }
+                // Staying in the hazard costs the same again next turn
+                hazard_value -= self.hazard_damage_at(&snake.body[0]);
             }
         }
-        if dead_snake_count == 2 {
-            self.stored_fast_heuristic.set(Some(i32::MAX));
-            return i32::MAX;
+        if alive_value == 0 {
+            return None;
         }
-        let v = health_value * 1 + length_value * 8 + death_value * 20;
-        self.stored_fast_heuristic.set(Some(v));
-        v
+        Some(
+            health_value * w.health
+                + hazard_value * w.hazard
+                + length_value * w.length
+                + alive_value * w.alive,
+        )
     }
 
+    // Territory of our team against the opponents, with penalties for snakes
+    // that do not fit in the area they can reach or are far from food
     fn flood_fill_heuristic(&self) -> i32 {
         let flood_fill = self.flood_fill();
+        let our_value = self.team_flood_fill_heuristic(self.team(), &flood_fill);
+        let opp_value: i32 = self
+            .teams
+            .iter()
+            .skip(1)
+            .map(|t| self.team_flood_fill_heuristic(t, &flood_fill))
+            .sum();
+        let v = our_value - opp_value;
+        self.stored_flood_fill_heuristic.set(Some(v));
+        v
+    }
+
+    fn team_flood_fill_heuristic(
+        &self,
+        team: &[usize],
+        flood_fill: &FloodFill,
+    ) -> i32 {
         let mut sum_value = 0;
         let mut danger_value = 0;
-        for f_idx in self.team {
-            if f_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                continue;
+        let mut food_value = 0;
+        let mut starving_value = 0;
+        for &idx in team {
+            if let Some(Some(snake)) = self.snakes.get(idx) {
+                let area = flood_fill.area(idx);
+                let ff_size = area.len() as i32;
+                sum_value += self.territory_value(area);
+                if ff_size < snake.body.len() as i32 {
+                    danger_value -= snake.body.len() as i32 - ff_size;
+                }
+
+                // Food is only worth going for if nobody gets there first,
+                // the further it is the more it costs and more so the hungrier we are
+                let nearest = self
+                    .food
+                    .iter()
+                    .filter_map(|f| flood_fill.distances[&idx][self.cell_index(f)])
+                    .min()
+                    .map(|d| d as i32);
+                let hunger = SNAKE_MAX_HEALTH - snake.health;
+                food_value -= nearest.unwrap_or(self.width + self.height) * hunger;
+                // Health runs out before the snake gets to eat
+                if nearest.unwrap_or(self.width + self.height) > snake.health {
+                    starving_value -= 1;
+                }
             }
-            match &self.snakes[f_idx] {
-                Some(snake) => {
-                    let ff_size = flood_fill.get(&f_idx).unwrap().len() as i32;
-                    sum_value += ff_size;
-                    if ff_size < snake.body.len() as i32 {
-                        danger_value -= snake.body.len() as i32 - ff_size;
-                    }
+        }
+        sum_value * self.weights.territory
+            + danger_value * self.weights.danger
+            + food_value * self.weights.food / 10
+            + starving_value * self.weights.starving
+    }
+
+    // Every hazard in a cell takes half of its worth as territory, stacked
+    // hazards discount it as many times as they deal damage
+    fn territory_value(&self, area: &[Coord]) -> i32 {
+        let halves: i32 = area
+            .iter()
+            .map(|c| (2 - self.hazards.iter().filter(|&h| h == c).count() as i32).max(0))
+            .sum();
+        halves / 2
+    }
+
+    // Damage taken by a head at coord, food in the cell protects from it
+    fn hazard_damage_at(&self, coord: &Coord) -> i32 {
+        if self.food.contains(coord) {
+            return 0;
+        }
+        self.hazards.iter().filter(|&h| h == coord).count() as i32 * self.rules.hazard_damage
+    }
+
+    // Voronoi partition of the board, a multi-source BFS from every head in
+    // lockstep. A body segment blocks its cell until the turn it vacates, a
+    // cell reached by several snakes in the same turn is contested and
+    // nobody expands from it
+    pub fn flood_fill(&self) -> FloodFill {
+        let cells = (self.width * self.height) as usize;
+        let mut flood_fill = FloodFill {
+            areas: BTreeMap::new(),
+            contested: Vec::new(),
+            distances: BTreeMap::new(),
+        };
+
+        // The turn every cell becomes free, the tail vacates after one turn,
+        // constrictor bodies never do
+        let mut free_at = vec![0u32; cells];
+        for snake in self.snakes.iter().flatten() {
+            let len = snake.body.len();
+            for (i, part) in snake.body.iter().enumerate() {
+                if self.in_bounds(part) {
+                    let t = match self.rules.mode {
+                        GameMode::Constrictor => u32::MAX,
+                        _ => (len - i) as u32,
+                    };
+                    let idx = self.cell_index(part);
+                    free_at[idx] = free_at[idx].max(t);
                 }
-                None => {}
             }
         }
-        for e_idx in self.opps {
-            if e_idx >= self.snakes.len() {
-                // Index out of range, treat as None
-                continue;
+
+        let mut reached = vec![false; cells];
+        let mut frontier: Vec<(usize, Coord)> = Vec::new();
+        for (i, snake) in self.snakes.iter().enumerate() {
+            if let Some(snake) = snake {
+                flood_fill.areas.insert(i, Vec::new());
+                flood_fill.distances.insert(i, vec![None; cells]);
+                let head = snake.body[0];
+                if self.in_bounds(&head) {
+                    let idx = self.cell_index(&head);
+                    reached[idx] = true;
+                    flood_fill.areas.get_mut(&i).unwrap().push(head);
+                    flood_fill.distances.get_mut(&i).unwrap()[idx] = Some(0);
+                    frontier.push((i, head));
+                }
             }
-            match &self.snakes[e_idx] {
-                Some(snake) => {
-                    let ff_size = flood_fill.get(&e_idx).unwrap().len() as i32;
-                    sum_value -= ff_size;
-                    if ff_size < snake.body.len() as i32 {
-                        danger_value += snake.body.len() as i32 - ff_size;
+        }
+
+        let mut distance = 0;
+        while !frontier.is_empty() {
+            distance += 1;
+            // Every snake reaching each cell this turn
+            let mut claims: BTreeMap<usize, (Coord, Vec<usize>)> = BTreeMap::new();
+            for &(i, coord) in frontier.iter() {
+                for neighbour in self.neighbours(&coord) {
+                    let idx = self.cell_index(&neighbour);
+                    if reached[idx] || free_at[idx] > distance {
+                        continue;
                     }
+                    let claim = claims.entry(idx).or_insert((neighbour, Vec::new()));
+                    if !claim.1.contains(&i) {
+                        claim.1.push(i);
+                    }
+                }
+            }
+
+            frontier.clear();
+            for (idx, (coord, snakes)) in claims {
+                reached[idx] = true;
+                for &i in snakes.iter() {
+                    flood_fill.distances.get_mut(&i).unwrap()[idx] = Some(distance);
+                }
+                if let [i] = snakes[..] {
+                    flood_fill.areas.get_mut(&i).unwrap().push(coord);
+                    frontier.push((i, coord));
+                } else {
+                    flood_fill.contested.push(coord);
                 }
-                None => {}
             }
         }
-        let v = sum_value * 1 + danger_value * 4;
-        self.stored_flood_fill_heuristic.set(Some(v));
-        v
+        flood_fill
     }
 
-    fn flood_fill(&self) -> HashMap<usize, Vec<Coord>> {
-        let mut mapping = HashMap::new();
-        let mut queue: Vec<(usize, Coord)> = self
-            .snakes
-            .iter()
-            .enumerate()
-            .filter(|(_, o)| o.is_some())
-            .map(|(i, s)| (i, s.as_ref().unwrap().body[0]))
-            .collect();
-        queue.sort_by_key(|&(i, _)| self.snakes[i].as_ref().unwrap().body.len());
-        let mut queue = VecDeque::from(queue);
-        for &(idx, _) in queue.iter() {
-            mapping.insert(idx, Vec::new());
+    fn in_bounds(&self, coord: &Coord) -> bool {
+        (0..self.width).contains(&coord.x) && (0..self.height).contains(&coord.y)
+    }
+
+    // Moves off one edge come back on the opposite side on a wrapped board
+    fn wrap(&self, coord: Coord) -> Coord {
+        match self.rules.topology {
+            Topology::Bounded => coord,
+            Topology::Wrapped => Coord {
+                x: coord.x.rem_euclid(self.width),
+                y: coord.y.rem_euclid(self.height),
+            },
         }
-        let mut visited = [false; 121];
-        while let Some((i, coord)) = queue.pop_front() {
-            if coord.x < 0 || coord.x > 10 || coord.y < 0 || coord.y > 10 {
-                continue;
-            }
-            let arr_idx = (coord.y * 11 + coord.x) as usize;
-            if visited[arr_idx] {
-                continue;
-            }
-            visited[arr_idx] = true;
-            mapping.get_mut(&i).unwrap().push(coord);
-            for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
-                let nx = coord.x + dx;
-                let ny = coord.y + dy;
-                if nx >= 0 && nx < 11 && ny >= 0 && ny < 11 {
-                    queue.push_back((i, Coord { x: nx, y: ny }));
+    }
+
+    fn cell_index(&self, coord: &Coord) -> usize {
+        (coord.y * self.width + coord.x) as usize
+    }
+
+    // Zobrist hash of the position with our_team being the side to move.
+    // Hazards never change during a search and are left out. A snake without
+    // a safe move is forced off the board until it is eliminated, only the
+    // cells on the board have keys
+    pub fn zobrist_hash(&self, our_team: bool) -> u64 {
+        let mut hash = if our_team { zobrist::our_turn() } else { 0 };
+        for (i, snake) in self.snakes.iter().enumerate() {
+            if let Some(snake) = snake {
+                for part in snake.body.iter().filter(|c| self.in_bounds(c)) {
+                    hash ^= zobrist::body(i, self.cell_index(part));
+                }
+                if self.in_bounds(&snake.body[0]) {
+                    hash ^= zobrist::head(i, self.cell_index(&snake.body[0]));
                 }
+                hash ^= zobrist::length(i, snake.body.len());
+                hash ^= zobrist::health(i, snake.health);
             }
         }
-        mapping
+        for food in self.food.iter() {
+            hash ^= zobrist::food(self.cell_index(food));
+        }
+        hash
     }
 
-    // This could be using team instead of index and then do the combined moves
-    pub fn simulate_move(&self, our_team: bool) -> Vec<([SnakeMove; 2], Self)> {
-        // reset stored heuristics since snakes have moved
-        self.stored_fast_heuristic.set(None);
-        self.stored_flood_fill_heuristic.set(None);
+    fn neighbours(&self, coord: &Coord) -> Vec<Coord> {
+        [(0, 1), (1, 0), (0, -1), (-1, 0)]
+            .iter()
+            .map(|(dx, dy)| {
+                self.wrap(Coord {
+                    x: coord.x + dx,
+                    y: coord.y + dy,
+                })
+            })
+            .filter(|c| self.in_bounds(c))
+            .collect()
+    }
 
-        let idx = if our_team { self.team } else { self.opps };
+    // Safe moves of every alive snake on one side, Down if a snake has none
+    fn safe_moves(&self, our_team: bool) -> Vec<Vec<SnakeMove>> {
         let mut moves = Vec::new();
-        let mut alive = [false; 4];
-        for i in idx {
+        for i in self.side(our_team) {
             if let Some(snake) = &self.snakes[i] {
-                alive[i] = true;
                 let mut m = snake.get_safe_moves(self, our_team);
-                if m.len() == 0 {
+                if m.is_empty() {
                     m.push(Movement::Down);
                 }
                 moves.push(
@@ -327,72 +505,124 @@ impl SimpleBoard {
                         .map(|&m| SnakeMove { id: i, mv: m })
                         .collect::<Vec<SnakeMove>>(),
                 );
-            } else {
-                moves.push(vec![SnakeMove {
-                    id: i,
-                    mv: Movement::Down,
-                }]);
             }
         }
+        moves
+    }
 
-        let mut simulations = Vec::new();
-        let team_moves: Vec<[SnakeMove; 2]> = cartesian_move(&moves[0], &moves[1]).collect();
-        for m in team_moves {
-            let next_pos = [
-                if alive[idx[0]] {
-                    self.snakes[idx[0]]
-                        .as_ref()
-                        .unwrap()
-                        .next_position(m.iter().find(|mv| mv.id == idx[0]).unwrap().mv)
-                } else {
-                    Coord { x: -2, y: -1 }
-                },
-                if alive[idx[1]] {
-                    self.snakes[idx[1]]
-                        .as_ref()
-                        .unwrap()
-                        .next_position(m.iter().find(|mv| mv.id == idx[1]).unwrap().mv)
-                } else {
-                    Coord { x: -1, y: -2 }
-                },
-            ];
-            if next_pos[0] == next_pos[1] {
-                continue;
-            }
+    // Teammates never move into the same cell
+    fn team_crash(&self, moves: &[SnakeMove]) -> bool {
+        let next_pos: Vec<(usize, Coord)> = moves
+            .iter()
+            .map(|mv| (mv.id, self.snakes[mv.id].as_ref().unwrap().next_position(mv.mv, self)))
+            .collect();
+        next_pos.iter().enumerate().any(|(a, (i, pos))| {
+            next_pos
+                .iter()
+                .skip(a + 1)
+                .any(|(j, other)| pos == other && self.same_team(*i, *j))
+        })
+    }
 
-            let mut next_board = self.clone();
-            if alive[idx[0]] {
-                next_board.snakes[idx[0]]
-                    .as_mut()
-                    .unwrap()
-                    .body
-                    .push_front(next_pos[0]);
+    // Every joint move of one side, all Down if there is none
+    pub fn side_moves(&self, our_team: bool) -> Vec<Vec<SnakeMove>> {
+        let moves = self.safe_moves(our_team);
+        let joint: Vec<Vec<SnakeMove>> = joint_moves(&moves)
+            .into_iter()
+            .filter(|m| !self.team_crash(m))
+            .collect();
+        if joint.is_empty() {
+            return vec![moves
+                .iter()
+                .map(|m| SnakeMove {
+                    id: m[0].id,
+                    mv: Movement::Down,
+                })
+                .collect()];
+        }
+        joint
+    }
 
-                if !next_board.food.contains(&next_pos[0]) {
-                    next_board.snakes[idx[0]].as_mut().unwrap().body.pop_back();
-                }
-                next_board.snakes[idx[0]].as_mut().unwrap().health -= 1;
-            }
-            if alive[idx[1]] {
-                next_board.snakes[idx[1]]
-                    .as_mut()
-                    .unwrap()
-                    .body
-                    .push_front(next_pos[1]);
+    // Moves and feeds the given snakes, nobody is eliminated
+    fn apply_moves(&self, moves: &[SnakeMove]) -> Self {
+        let next_pos: Vec<(usize, Coord)> = moves
+            .iter()
+            .map(|mv| (mv.id, self.snakes[mv.id].as_ref().unwrap().next_position(mv.mv, self)))
+            .collect();
 
-                if !next_board.food.contains(&next_pos[1]) {
-                    next_board.snakes[idx[1]].as_mut().unwrap().body.pop_back();
+        let mut next_board = self.clone();
+        // reset stored heuristics since snakes have moved
+        next_board.stored_fast_heuristic.set(None);
+        next_board.stored_flood_fill_heuristic.set(None);
+        for &(i, pos) in next_pos.iter() {
+            let snake = next_board.snakes[i].as_mut().unwrap();
+            snake.body.push_front(pos);
+            snake.body.pop_back();
+            snake.health -= 1;
+            snake.health = (snake.health - self.hazard_damage_at(&pos)).max(0);
+            // Growing stacks the tail, it stays in place for one turn
+            if self.food.contains(&pos) {
+                let tail = *snake.body.back().unwrap();
+                snake.body.push_back(tail);
+                snake.health = SNAKE_MAX_HEALTH;
+            }
+            // Constrictor snakes grow every turn and never get hungry
+            if self.rules.mode == GameMode::Constrictor {
+                snake.health = SNAKE_MAX_HEALTH;
+                if !snake.tail_is_stacked() {
+                    let tail = *snake.body.back().unwrap();
+                    snake.body.push_back(tail);
                 }
-                next_board.snakes[idx[1]].as_mut().unwrap().health -= 1;
             }
+        }
+
+        next_board
+            .food
+            .retain(|f| !next_pos.iter().any(|(_, pos)| pos == f));
+        if self.rules.mode == GameMode::Constrictor {
+            next_board.food.clear();
+        }
+        next_board
+    }
+
+    // Plays one full turn where every snake moves at the same time
+    pub fn simulate_joint(&self, moves: &[SnakeMove]) -> Self {
+        let mut next_board = self.apply_moves(moves);
+        next_board.kill_snakes();
+        next_board.share_squad_attributes();
+        next_board
+    }
+
+    // Our joint move that leaves our team the most room, cheap enough to use
+    // when there is no time to search. The first of equal moves wins
+    pub fn largest_area_move(&self) -> Vec<SnakeMove> {
+        self.simulate_move(true)
+            .into_iter()
+            .min_by_key(|(_, board)| {
+                let flood_fill = board.flood_fill();
+                Reverse(self.team().iter().map(|&i| flood_fill.area(i).len()).sum::<usize>())
+            })
+            .map(|(moves, _)| moves)
+            .unwrap_or_default()
+    }
+
+    // Simulates every joint move of one side, the other side stands still
+    pub fn simulate_move(&self, our_team: bool) -> Vec<(Vec<SnakeMove>, Self)> {
+        let moves = self.safe_moves(our_team);
 
-            next_board
-                .food
-                .retain(|f| f != &next_pos[0] && f != &next_pos[1]);
+        let mut simulations = Vec::new();
+        for m in joint_moves(&moves) {
+            if self.team_crash(&m) {
+                continue;
+            }
+            let mut next_board = self.apply_moves(&m);
 
             //info!("Simulating move: {:?} -> \n{}", m, next_board);
 
-            if !our_team { next_board.kill_snakes(); }
+            if !our_team {
+                next_board.kill_snakes();
+                next_board.share_squad_attributes();
+            }
 
             //info!("Killed snakes: \n{}", next_board);
 
@@ -401,18 +631,15 @@ impl SimpleBoard {
 
         // Det här behöver ersättas med lösning för att låta en leva om de "måste" huvudkrocka
         // Det eller att det inte finns några safe moves är enda sätten simulations kan ge 0 moves
-        if simulations.len() == 0 {
+        if simulations.is_empty() {
             return vec![(
-                [
-                    SnakeMove {
-                        id: idx[0],
-                        mv: Movement::Down,
-                    },
-                    SnakeMove {
-                        id: idx[1],
+                moves
+                    .iter()
+                    .map(|m| SnakeMove {
+                        id: m[0].id,
                         mv: Movement::Down,
-                    },
-                ],
+                    })
+                    .collect(),
                 self.clone(),
             )];
         }
@@ -423,34 +650,73 @@ impl SimpleBoard {
         let mut kill_idxs = Vec::new();
         for (i, o_snake) in self.snakes.iter().enumerate() {
             if let Some(snake) = o_snake {
-                if snake.health == 0
-                    || simple_out_of_bounds(&snake.body[0], &Movement::None)
-                    || snake.collision_with_snakes(&self)
+                if snake.health <= 0
+                    || simple_out_of_bounds(self, &snake.body[0], &Movement::None)
+                    || snake.collision_with_snakes(self, i)
                 {
                     kill_idxs.push(i);
                     continue;
                 }
             }
         }
+        if self.rules.shared_elimination {
+            let teammates: Vec<usize> = self
+                .teams
+                .iter()
+                .filter(|t| t.iter().any(|i| kill_idxs.contains(i)))
+                .flatten()
+                .copied()
+                .collect();
+            kill_idxs.extend(teammates);
+        }
         for idx in kill_idxs {
             self.snakes[idx] = None;
         }
     }
+
+    // Squads can share health and length, every member gets the best of the team
+    fn share_squad_attributes(&mut self) {
+        if !self.rules.shared_health && !self.rules.shared_length {
+            return;
+        }
+        for team in self.teams.iter() {
+            let alive: Vec<&SimpleSnake> = team
+                .iter()
+                .filter_map(|&i| self.snakes[i].as_ref())
+                .collect();
+            let (Some(health), Some(length)) = (
+                alive.iter().map(|s| s.health).max(),
+                alive.iter().map(|s| s.body.len()).max(),
+            ) else {
+                continue;
+            };
+            for &i in team.iter() {
+                if let Some(snake) = self.snakes[i].as_mut() {
+                    if self.rules.shared_health {
+                        snake.health = health;
+                    }
+                    if self.rules.shared_length {
+                        while snake.body.len() < length {
+                            let tail = *snake.body.back().unwrap();
+                            snake.body.push_back(tail);
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
 impl std::fmt::Display for SimpleBoard {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         /* build board representation string */
-        let mut board: String = "\n|:---------:|".to_owned();
-        for y in (0..11).rev() {
+        let border = format!("|:{}:|", "-".repeat((self.width - 2).max(0) as usize));
+        let mut board: String = format!("\n{}", border);
+        for y in (0..self.height).rev() {
             board += "\n|";
-            for x in 0..11 {
-                let coord = Coord {
-                    x: x as i32,
-                    y: y as i32,
-                };
-                let piece: String = if self.food.contains(&coord) {
-                    "f".to_string()
-                } else if let Some(snake) = self
+            for x in 0..self.width {
+                let coord = Coord { x, y };
+                // Snakes are drawn over hazards, they can stand in them
+                let piece: String = if let Some(snake) = self
                     .snakes
                     .iter()
                     .filter_map(|s| s.as_ref())
@@ -461,6 +727,10 @@ impl std::fmt::Display for SimpleBoard {
                     } else {
                         "s".to_string()
                     }
+                } else if self.food.contains(&coord) {
+                    "f".to_string()
+                } else if self.hazards.contains(&coord) {
+                    "b".to_string()
                 } else {
                     " ".to_string()
                 };
@@ -468,22 +738,43 @@ impl std::fmt::Display for SimpleBoard {
             }
             board += "|";
         }
-        board += "\n|:---------:|";
+        board += &format!("\n{}", border);
 
         write!(f, "{}", board)
     }
 }
 
-// Galenskap hehe
-fn cartesian_move<'a>(
-    v1: &'a [SnakeMove],
-    v2: &'a [SnakeMove],
-) -> impl Iterator<Item = [SnakeMove; 2]> + 'a {
-    let ret = v1
-        .iter()
-        .flat_map(move |&m1| v2.iter().map(move |&m2| [m1, m2]));
-    //info!("Cartesian product from {:?} and {:?} = {:?}", v1, v2, ret.clone().collect::<Vec<_>>());
-    ret
+// Galenskap hehe, every combination of one move per snake
+fn joint_moves(options: &[Vec<SnakeMove>]) -> Vec<Vec<SnakeMove>> {
+    options.iter().fold(vec![Vec::new()], |acc, snake_moves| {
+        acc.iter()
+            .flat_map(|prefix| {
+                snake_moves.iter().map(move |&m| {
+                    let mut joint = prefix.clone();
+                    joint.push(m);
+                    joint
+                })
+            })
+            .collect()
+    })
+}
+
+// Result of SimpleBoard::flood_fill, by snake index
+#[derive(Debug, Clone)]
+pub struct FloodFill {
+    // Cells each snake reaches before anyone else, its head included
+    pub areas: BTreeMap<usize, Vec<Coord>>,
+    // Cells two or more snakes reach in the same turn
+    pub contested: Vec<Coord>,
+    // Turns for each snake to reach the cells it owns or contests, by cell
+    // index, None for cells someone else gets to first
+    pub distances: BTreeMap<usize, Vec<Option<u32>>>,
+}
+
+impl FloodFill {
+    pub fn area(&self, snake: usize) -> &[Coord] {
+        self.areas.get(&snake).map_or(&[], |a| a.as_slice())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -503,37 +794,23 @@ impl SimpleSnake {
     fn get_safe_moves(&self, simple_board: &SimpleBoard, our_team: bool) -> Vec<Movement> {
         let mut m_v = Movement::all();
         let head = &self.body[0];
+        // The neck can be across the edge of a wrapped board
         if self.body.len() > 1 {
-            let neck = &self.body[1];
-            if neck.x < head.x {
-                let idx = m_v.iter().position(|m| matches!(m, Movement::Left)).unwrap();
-                m_v.remove(idx);
-            }
-            if neck.x > head.x {
-                let idx = m_v.iter().position(|m| matches!(m, Movement::Right)).unwrap();
-                m_v.remove(idx);
-            }
-            if neck.y < head.y {
-                let idx = m_v.iter().position(|m| matches!(m, Movement::Down)).unwrap();
-                m_v.remove(idx);
-            }
-            if neck.y > head.y {
-                let idx = m_v.iter().position(|m| matches!(m, Movement::Up)).unwrap();
-                m_v.remove(idx);
-            }
+            let neck = self.body[1];
+            m_v.retain(|&m| self.next_position(m, simple_board) != neck);
         }
-        
+
         m_v.retain(|&m| {
-            !simple_out_of_bounds(&head, &m)
+            !simple_out_of_bounds(simple_board, head, &m)
                 && (if our_team { !self.team_collision(simple_board, m) } else { !self.opps_collision(simple_board, m) })
         });
 
         m_v
     }
 
-    fn next_position(&self, movement: Movement) -> Coord {
+    fn next_position(&self, movement: Movement, simple_board: &SimpleBoard) -> Coord {
         let head = &self.body[0];
-        match movement {
+        let next = match movement {
             Movement::Up => Coord {
                 x: head.x,
                 y: head.y + 1,
@@ -550,13 +827,23 @@ impl SimpleSnake {
                 x: head.x + 1,
                 y: head.y,
             },
-            Movement::None => head.clone(),
+            Movement::None => *head,
+        };
+        simple_board.wrap(next)
+    }
+
+    // The tail cell is vacated next turn unless the snake just ate,
+    // in constrictor games tails never move
+    fn moving_tail(&self, simple_board: &SimpleBoard) -> Option<Coord> {
+        if simple_board.rules.mode == GameMode::Constrictor || self.tail_is_stacked() {
+            return None;
         }
+        self.body.back().copied()
     }
 
-    fn collision_with_body(&self, movement: Movement) -> bool {
-        let next_pos = self.next_position(movement);
-        self.body.iter().any(|b| b == &next_pos)
+    fn tail_is_stacked(&self) -> bool {
+        let len = self.body.len();
+        len > 1 && self.body[len - 1] == self.body[len - 2]
     }
 
     fn team_collision(
@@ -564,46 +851,49 @@ impl SimpleSnake {
         simple_board: &SimpleBoard,
         movement: Movement,
     ) -> bool {
-        let next_pos = self.next_position(movement);
-        for idx in simple_board.team {
+        let next_pos = self.next_position(movement, simple_board);
+        for &idx in simple_board.team() {
             if let Some(snake) = &simple_board.snakes[idx] {
-                if let Some(&pos) = snake.body.back() {
+                if let Some(pos) = snake.moving_tail(simple_board) {
                     if pos == next_pos {
                         return false;
                     }
                 }
             }
         }
-        for idx in simple_board.opps {
+        for idx in simple_board.opps() {
             if let Some(snake) = &simple_board.snakes[idx] {
-                if let Some(&pos) = snake.body.back() {
+                if let Some(pos) = snake.moving_tail(simple_board) {
                     if pos == next_pos {
                         let head = snake.body.front().unwrap();
-                        for (dx, dy) in [(0, 1), (1, 0), (0, -1), (-1, 0)] {
-                            let nx = head.x + dx;
-                            let ny = head.y + dy;
-                            if nx >= 0 && nx < 11 && ny >= 0 && ny < 11 {
-                                let new_coord = Coord { x: nx, y: ny };
-                                if simple_board.food.contains(&new_coord) {
-                                    return true;
-                                }
-                            }
-                        }
-                        return false;
+                        return simple_board
+                            .neighbours(head)
+                            .iter()
+                            .any(|c| simple_board.food.contains(c));
                     }
                 }
             }
         }
 
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
+        let passable = |j: usize| {
+            simple_board.rules.allow_body_collisions
+                && simple_board.team().contains(&j)
+                && simple_board.snakes[j].as_ref() != Some(self)
+        };
+        for (_, s) in simple_board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(j, s)| s.is_some() && !passable(*j))
+        {
             let collision = s
                 .as_ref()
-                .map_or(false, |snake| snake.body.contains(&next_pos));
+                .is_some_and(|snake| snake.body.contains(&next_pos));
             if collision {
                 return true;
             }
         }
-        return false;
+        false
     }
     
     fn opps_collision(
@@ -611,23 +901,35 @@ impl SimpleSnake {
         simple_board: &SimpleBoard,
         movement: Movement,
     ) -> bool {
-        let next_pos = self.next_position(movement);
-        let mut any_collision = false;
-        let mut dead = false;
-        for idx in simple_board.opps {
+        let next_pos = self.next_position(movement, simple_board);
+        for idx in simple_board.opps() {
             if let Some(snake) = &simple_board.snakes[idx] {
-                if let Some(&pos) = snake.body.back() {
+                if let Some(pos) = snake.moving_tail(simple_board) {
                     if pos == next_pos {
                         return false;
                     }
                 }
             }
         }
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
+
+        // Enemy squads can move through each other like ours
+        let passable = |j: usize| {
+            simple_board.rules.allow_body_collisions
+                && simple_board.snakes[j].as_ref() != Some(self)
+                && simple_board.teams.iter().skip(1).any(|t| {
+                    t.contains(&j) && t.iter().any(|&i| simple_board.snakes[i].as_ref() == Some(self))
+                })
+        };
+        for (_, s) in simple_board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(j, s)| s.is_some() && !passable(*j))
+        {
             let collision = s
                 .as_ref()
-                .map_or(false, |snake| snake.body.contains(&next_pos));
-            if collision{
+                .is_some_and(|snake| snake.body.contains(&next_pos));
+            if collision {
                 // Only check length if collision is with the head, otherwise always dead
                 if s.as_ref().unwrap().body.front().unwrap() == &next_pos {
                     if s.as_ref().unwrap().body.len() >= self.body.len() {
@@ -639,19 +941,25 @@ impl SimpleSnake {
                 }
             }
         }
-        return false;
+        false
     }
 
     fn collision_with_snakes (
         &self,
         simple_board: &SimpleBoard,
+        idx: usize,
     ) -> bool {
         let head = self.body.front().unwrap();
 
-        for s in simple_board.snakes.iter().filter(|s| s.is_some()) {
+        for (_, s) in simple_board
+            .snakes
+            .iter()
+            .enumerate()
+            .filter(|(j, s)| s.is_some() && !simple_board.can_pass_through(idx, *j))
+        {
             let collision = s
                 .as_ref()
-                .map_or(false, |snake| snake.body.contains(&head));
+                .is_some_and(|snake| snake.body.contains(head));
             if collision {
                 // Only check length if collision is with the head, otherwise always dead
                 let snek = s.as_ref().unwrap(); 
@@ -669,17 +977,21 @@ impl SimpleSnake {
                 }
             }
         }
-        return false;
+        false
     }
 }
 
-fn simple_out_of_bounds(coord: &Coord, movement: &Movement) -> bool {
+fn simple_out_of_bounds(simple_board: &SimpleBoard, coord: &Coord, movement: &Movement) -> bool {
+    let (width, height) = (simple_board.width, simple_board.height);
+    if simple_board.rules.topology == Topology::Wrapped {
+        return !simple_board.in_bounds(coord);
+    }
     match movement {
-        Movement::Up => coord.y == 10,
-        Movement::Down => coord.y == 0,
-        Movement::Left => coord.x == 0,
-        Movement::Right => coord.x == 10,
-        Movement::None => coord.x < 0 || coord.x > 10 || coord.y < 0 || coord.y > 10,
+        Movement::Up => coord.y >= height - 1,
+        Movement::Down => coord.y <= 0,
+        Movement::Left => coord.x <= 0,
+        Movement::Right => coord.x >= width - 1,
+        Movement::None => coord.x < 0 || coord.x >= width || coord.y < 0 || coord.y >= height,
     }
 }
 
@@ -687,7 +999,8 @@ fn simple_out_of_bounds(coord: &Coord, movement: &Movement) -> bool {
 mod tests {
     use std::collections::VecDeque;
     use crate::{Coord, Movement};
-    use super::{SimpleBoard, SimpleSnake};
+    use super::{joint_moves, HeuristicWeights, SimpleBoard, SimpleRules, SimpleSnake, SnakeMove};
+    use crate::{GameMode, Topology};
 
     fn create_snake_at(body: Vec<Coord>, health: i32) -> SimpleSnake {
         let mut body_deque = VecDeque::new();
@@ -702,10 +1015,14 @@ mod tests {
 
     fn basic_board_with_snake(snake: SimpleSnake) -> SimpleBoard {
         SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake), None, None, None],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         }
@@ -724,6 +1041,87 @@ mod tests {
         assert_eq!(moves, vec![Movement::Right]);
     }
 
+    #[test]
+    fn test_small_board_corner() {
+        // Snake in the top right corner of a 7x7 board, only DOWN is possible
+        let snake = create_snake_at(
+            vec![Coord { x: 6, y: 6 }, Coord { x: 5, y: 6 }],
+            100,
+        );
+        let mut board = basic_board_with_snake(snake.clone());
+        board.width = 7;
+        board.height = 7;
+
+        let moves = snake.get_safe_moves(&board, true);
+        assert_eq!(moves, vec![Movement::Down]);
+    }
+
+    #[test]
+    fn test_large_board_beyond_eleven() {
+        // On a 19x19 board the snake can keep going right past x = 10
+        let snake = create_snake_at(
+            vec![Coord { x: 10, y: 10 }, Coord { x: 9, y: 10 }],
+            100,
+        );
+        let mut board = basic_board_with_snake(snake.clone());
+        board.width = 19;
+        board.height = 19;
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Down, Movement::Right]);
+    }
+
+    #[test]
+    fn test_non_square_board() {
+        // 7 wide, 5 high: the top edge is at y = 4 but x = 6 is still free
+        let snake = create_snake_at(
+            vec![Coord { x: 5, y: 4 }, Coord { x: 4, y: 4 }],
+            100,
+        );
+        let mut board = basic_board_with_snake(snake.clone());
+        board.width = 7;
+        board.height = 5;
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Down, Movement::Right]);
+
+        // Flood fill reaches every cell exactly once
+        let flood_fill = board.flood_fill();
+        assert_eq!(flood_fill.area(0).len(), 35);
+
+        // Rendering has one row per y and one column per x
+        let rendered = format!("{}", board);
+        let rows: Vec<&str> = rendered.lines().skip(1).collect();
+        assert_eq!(rows.len(), 5 + 2);
+        assert!(rows.iter().all(|r| r.len() == 7 + 2));
+    }
+
+    #[test]
+    fn test_simple_board_dimensions_from_board() {
+        let board = crate::Board {
+            height: 5,
+            width: 7,
+            food: vec![],
+            snakes: vec![],
+            hazards: vec![],
+        };
+        let game_info = crate::GameInfo {
+            id: String::from("game"),
+            timeout: 500,
+            ruleset: crate::Ruleset::default(),
+            agent_ids: vec![],
+            agent_moves: vec![],
+            agent_latency: vec![],
+            weights: Default::default(),
+            engine: Default::default(),
+        };
+        let simple_board = SimpleBoard::from(&board, &game_info);
+        assert_eq!(simple_board.width, 7);
+        assert_eq!(simple_board.height, 5);
+    }
+
     #[test]
     fn test_own_tail() {
         // Snake is in a tight U shape; only Right is safe (moving to tail)
@@ -767,7 +1165,7 @@ mod tests {
     #[test]
     fn test_enemy_collision_blocked() {
         // Snake near enemy that blocks the right
-        let mut snake = create_snake_at(
+        let snake = create_snake_at(
             vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }],
             100,
         );
@@ -777,10 +1175,14 @@ mod tests {
         );
 
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy_snake), None, None],
-            team: [0, 2],
-            opps: [1, 3],
+            teams: vec![vec![0, 2], vec![1, 3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -814,10 +1216,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(teammate), None, None],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -859,10 +1265,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![Coord { x: 0, y: 5 }],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy1), Some(enemy2), None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -895,10 +1305,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(teammate), None, None],
-            team: [2, 3],
-            opps: [0, 1],
+            teams: vec![vec![2, 3], vec![0, 1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -940,10 +1354,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![Coord { x: 0, y: 5 }],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy1), Some(enemy2), None],
-            team: [1, 2],
-            opps: [0, 3],
+            teams: vec![vec![1, 2], vec![0, 3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -975,10 +1393,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy), None, None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1010,10 +1432,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake.clone()), Some(enemy), None, None],
-            team: [1, 3],
-            opps: [0, 2],
+            teams: vec![vec![1, 3], vec![0, 2]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1045,10 +1471,14 @@ mod tests {
         );
         
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![Some(snake), Some(enemy.clone()), None, None],
-            team: [0, 3],
-            opps: [1, 2],
+            teams: vec![vec![0, 3], vec![1, 2]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1067,15 +1497,19 @@ mod tests {
         let snake4 = create_snake_at(vec![Coord { x: 1, y: 9 }], 100);
 
         let board = SimpleBoard {
+            width: 11,
+            height: 11,
             food: vec![],
+            hazards: vec![],
             snakes: vec![
                 Some(snake1),
                 Some(snake2),
                 Some(snake3),
                 Some(snake4),
             ],
-            team: [0, 1],
-            opps: [2, 3],
+            teams: vec![vec![0, 1], vec![2, 3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
             stored_fast_heuristic: std::cell::Cell::new(None),
             stored_flood_fill_heuristic: std::cell::Cell::new(None),
         };
@@ -1142,4 +1576,846 @@ mod tests {
         // Assert that all snakes are None (dead)
         assert!(sim2[0].1.snakes.iter().all(|s| s.is_none()));
     }
+
+    #[test]
+    fn test_joint_moves_any_number_of_snakes() {
+        let options: Vec<Vec<SnakeMove>> = (0..3)
+            .map(|id| {
+                vec![
+                    SnakeMove { id, mv: Movement::Up },
+                    SnakeMove { id, mv: Movement::Left },
+                ]
+            })
+            .collect();
+        let joint = joint_moves(&options);
+        assert_eq!(joint.len(), 8);
+        assert!(joint.iter().all(|m| m.len() == 3));
+        assert_eq!(joint_moves(&[]).len(), 1);
+    }
+
+    #[test]
+    fn test_duel() {
+        let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }], 100);
+        let enemy = create_snake_at(vec![Coord { x: 9, y: 9 }, Coord { x: 9, y: 10 }], 100);
+
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        let ours = board.simulate_move(true);
+        assert_eq!(ours.len(), 3);
+        assert!(ours.iter().all(|(m, _)| m.len() == 1 && m[0].id == 0));
+        let theirs = board.simulate_move(false);
+        assert_eq!(theirs.len(), 3);
+        assert!(theirs.iter().all(|(m, _)| m.len() == 1 && m[0].id == 1));
+    }
+
+    #[test]
+    fn test_free_for_all() {
+        // Our snake against seven opponents, each on their own team
+        let heads = [(1, 1), (9, 1), (1, 9), (9, 9), (5, 1), (5, 9), (1, 5), (9, 5)];
+        let snakes = heads
+            .iter()
+            .map(|&(x, y)| Some(create_snake_at(vec![Coord { x, y }], 100)))
+            .collect();
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes,
+            teams: (0..8).map(|i| vec![i]).collect(),
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        assert_eq!(board.simulate_move(true).len(), 4);
+        let theirs = board.simulate_move(false);
+        assert_eq!(theirs.len(), 4_usize.pow(7));
+        assert!(theirs.iter().all(|(m, _)| m.len() == 7));
+        assert_eq!(board.heuristic(true), 28 - 7 * 28);
+    }
+
+    #[test]
+    fn test_squad_of_three() {
+        // Three teammates side by side never move into the same cell
+        let snakes = vec![
+            Some(create_snake_at(vec![Coord { x: 4, y: 5 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 5, y: 6 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 6, y: 5 }], 100)),
+            Some(create_snake_at(vec![Coord { x: 0, y: 0 }], 100)),
+        ];
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes,
+            teams: vec![vec![0, 1, 2], vec![3]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        let sims = board.simulate_move(true);
+        assert!(!sims.is_empty());
+        for (moves, next_board) in sims.iter() {
+            assert_eq!(moves.len(), 3);
+            let heads: Vec<Coord> = (0..3)
+                .map(|i| next_board.snakes[i].as_ref().unwrap().body[0])
+                .collect();
+            assert!(heads[0] != heads[1] && heads[0] != heads[2] && heads[1] != heads[2]);
+        }
+    }
+
+    #[test]
+    fn test_team_heuristic() {
+        let mut board = basic_board_with_snake(create_snake_at(vec![Coord { x: 1, y: 1 }], 10));
+        board.snakes[2] = Some(create_snake_at(vec![Coord { x: 5, y: 5 }], 100));
+        // One snake each, ours is hungry
+        assert_eq!(board.heuristic(true), -10);
+
+        board.stored_fast_heuristic.set(None);
+        board.snakes[0] = None;
+        assert_eq!(board.heuristic(true), i32::MIN);
+
+        board.stored_fast_heuristic.set(None);
+        board.snakes[0] = Some(create_snake_at(vec![Coord { x: 1, y: 1 }], 100));
+        board.snakes[2] = None;
+        assert_eq!(board.heuristic(true), i32::MAX);
+    }
+
+    #[test]
+    fn test_eating_stacks_tail() {
+        let snake = create_snake_at(
+            vec![
+                Coord { x: 1, y: 1 }, // head
+                Coord { x: 1, y: 0 }, // neck
+                Coord { x: 0, y: 0 },
+            ],
+            50,
+        );
+        let mut board = basic_board_with_snake(snake);
+        board.food = vec![Coord { x: 1, y: 2 }];
+
+        let sims = board.simulate_move(true);
+        let (_, next_board) = sims
+            .iter()
+            .find(|(m, _)| m[0].mv == Movement::Up)
+            .unwrap();
+        let eaten = next_board.snakes[0].as_ref().unwrap();
+        assert_eq!(eaten.health, 100);
+        assert_eq!(
+            eaten.body,
+            VecDeque::from(vec![
+                Coord { x: 1, y: 2 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 1, y: 0 },
+            ])
+        );
+        assert!(next_board.food.is_empty());
+        assert_eq!(eaten.moving_tail(next_board), None);
+    }
+
+    #[test]
+    fn test_stacked_tail_not_safe() {
+        // Teammate just ate so its tail stays in place next turn
+        let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 0, y: 1 }], 100);
+        let teammate = create_snake_at(
+            vec![
+                Coord { x: 2, y: 3 },
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 },
+                Coord { x: 2, y: 1 },
+            ],
+            100,
+        );
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake.clone()), Some(teammate)],
+            teams: vec![vec![0, 1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Down]);
+    }
+
+    fn squad_rules() -> SimpleRules {
+        SimpleRules {
+            mode: GameMode::Squad,
+            allow_body_collisions: true,
+            shared_elimination: true,
+            shared_health: true,
+            shared_length: true,
+            ..SimpleRules::default()
+        }
+    }
+
+    #[test]
+    fn test_squad_body_collisions() {
+        // Squad members can move through each other, but not through themselves
+        let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }], 100);
+        let teammate = create_snake_at(
+            vec![Coord { x: 3, y: 2 }, Coord { x: 2, y: 2 }, Coord { x: 2, y: 1 }, Coord { x: 2, y: 0 }],
+            100,
+        );
+        let mut board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake.clone()), Some(teammate)],
+            teams: vec![vec![0, 1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Left]);
+
+        board.rules = squad_rules();
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Left, Movement::Right]);
+
+        let sims = board.simulate_move(true);
+        let (_, after_ours) = sims.iter().find(|(m, _)| m[0].mv == Movement::Right).unwrap();
+        let after_turn = after_ours.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes.iter().all(|s| s.is_some()));
+    }
+
+    #[test]
+    fn test_squad_shared_elimination_and_attributes() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 30);
+        let teammate = create_snake_at(
+            vec![Coord { x: 8, y: 8 }, Coord { x: 8, y: 7 }, Coord { x: 8, y: 6 }],
+            90,
+        );
+        let enemy = create_snake_at(
+            vec![
+                Coord { x: 0, y: 0 },
+                Coord { x: 1, y: 0 },
+                Coord { x: 1, y: 1 },
+                Coord { x: 0, y: 1 },
+                Coord { x: 0, y: 2 },
+            ],
+            100,
+        );
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(teammate), Some(enemy)],
+            teams: vec![vec![0, 1], vec![2]],
+            rules: squad_rules(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        // Enemy is trapped in the corner, our squad shares health and length
+        let ours = board.simulate_move(true)[0].1.clone();
+        let after_turn = ours.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes[2].is_none());
+        let a = after_turn.snakes[0].as_ref().unwrap();
+        let b = after_turn.snakes[1].as_ref().unwrap();
+        assert_eq!(a.health, 89);
+        assert_eq!(b.health, 89);
+        assert_eq!(a.body.len(), 3);
+
+        // One squad member dying takes the whole squad with it
+        let mut doomed = board.clone();
+        doomed.snakes[0].as_mut().unwrap().health = 1;
+        let ours = doomed.simulate_move(true)[0].1.clone();
+        let after_turn = ours.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes[0].is_none());
+        assert!(after_turn.snakes[1].is_none());
+    }
+
+    #[test]
+    fn test_enemy_squads() {
+        // Opponents in the same squad are one team, the others play alone
+        let snake = |id: &str, squad: &str, body: &[(i32, i32)]| {
+            let body: Vec<Coord> = body.iter().map(|&(x, y)| Coord { x, y }).collect();
+            crate::Battlesnake {
+                id: id.to_string(),
+                name: id.to_string(),
+                health: 100,
+                head: body[0],
+                length: body.len() as i32,
+                body,
+                latency: String::new(),
+                shout: None,
+                squad: squad.to_string(),
+                customizations: Default::default(),
+            }
+        };
+        let board = crate::Board {
+            height: 11,
+            width: 11,
+            food: vec![],
+            snakes: vec![
+                snake("us", "a", &[(9, 9), (9, 8)]),
+                snake("b1", "b", &[(1, 1), (1, 0)]),
+                snake("b2", "b", &[(3, 2), (2, 2), (2, 1), (2, 0)]),
+                snake("c", "", &[(5, 9)]),
+                snake("d", "", &[(9, 5)]),
+            ],
+            hazards: vec![],
+        };
+        let game_info = crate::GameInfo {
+            id: String::from("game"),
+            timeout: 500,
+            ruleset: crate::Ruleset::default(),
+            agent_ids: vec![String::from("us")],
+            agent_moves: vec![vec![]],
+            agent_latency: vec![Default::default()],
+            weights: Default::default(),
+            engine: Default::default(),
+        };
+        let mut simple_board = SimpleBoard::from(&board, &game_info);
+        assert_eq!(simple_board.teams, vec![vec![0], vec![1, 2], vec![3], vec![4]]);
+
+        // Squad rules let the enemy squad through each other too
+        let enemy = simple_board.snakes[1].clone().unwrap();
+        let mut moves = enemy.get_safe_moves(&simple_board, false);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Left]);
+        simple_board.rules = squad_rules();
+        let mut moves = enemy.get_safe_moves(&simple_board, false);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Left, Movement::Right]);
+
+        // One member of the enemy squad dying takes the other with it
+        simple_board.snakes[1].as_mut().unwrap().health = 1;
+        let ours = simple_board.simulate_move(true)[0].1.clone();
+        let after_turn = ours.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes[1].is_none());
+        assert!(after_turn.snakes[2].is_none());
+        assert!(after_turn.snakes[3].is_some() && after_turn.snakes[4].is_some());
+    }
+
+    fn wrapped_board(snakes: Vec<Option<SimpleSnake>>, teams: Vec<Vec<usize>>) -> SimpleBoard {
+        SimpleBoard {
+            width: 7,
+            height: 5,
+            food: vec![],
+            hazards: vec![],
+            snakes,
+            teams,
+            rules: SimpleRules {
+                mode: GameMode::Wrapped,
+                topology: Topology::Wrapped,
+                ..SimpleRules::default()
+            },
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        }
+    }
+
+    #[test]
+    fn test_wrapped_corner() {
+        // Every direction except back into the neck is open on a torus
+        let snake = create_snake_at(vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }], 100);
+        let board = wrapped_board(vec![Some(snake.clone())], vec![vec![0]]);
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Down, Movement::Left]);
+
+        let sims = board.simulate_move(true);
+        let heads: Vec<Coord> = sims
+            .iter()
+            .map(|(_, b)| b.snakes[0].as_ref().unwrap().body[0])
+            .collect();
+        assert!(heads.contains(&Coord { x: 6, y: 0 }));
+        assert!(heads.contains(&Coord { x: 0, y: 4 }));
+        let after_turn = sims[0].1.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes[0].is_some());
+    }
+
+    #[test]
+    fn test_wrapped_neck_across_edge() {
+        // The neck is across the left edge, only Left goes back into it
+        let snake = create_snake_at(vec![Coord { x: 0, y: 2 }, Coord { x: 6, y: 2 }], 100);
+        let board = wrapped_board(vec![Some(snake.clone())], vec![vec![0]]);
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Down, Movement::Right]);
+    }
+
+    #[test]
+    fn test_wrapped_collision_across_edge() {
+        // The cell across the left edge is occupied by an enemy body
+        let snake = create_snake_at(vec![Coord { x: 0, y: 2 }, Coord { x: 1, y: 2 }], 100);
+        let enemy = create_snake_at(
+            vec![Coord { x: 6, y: 3 }, Coord { x: 6, y: 2 }, Coord { x: 6, y: 1 }],
+            100,
+        );
+        let board = wrapped_board(vec![Some(snake.clone()), Some(enemy)], vec![vec![0], vec![1]]);
+
+        let mut moves = snake.get_safe_moves(&board, true);
+        moves.sort();
+        assert_eq!(moves, vec![Movement::Up, Movement::Down]);
+    }
+
+    #[test]
+    fn test_wrapped_flood_fill() {
+        let snake = create_snake_at(vec![Coord { x: 0, y: 0 }], 100);
+        let board = wrapped_board(vec![Some(snake)], vec![vec![0]]);
+        let neighbours = board.neighbours(&Coord { x: 0, y: 0 });
+        assert_eq!(neighbours.len(), 4);
+        assert!(neighbours.contains(&Coord { x: 6, y: 0 }));
+        assert!(neighbours.contains(&Coord { x: 0, y: 4 }));
+        assert_eq!(board.flood_fill().area(0).len(), 35);
+    }
+
+    #[test]
+    fn test_hazard_damage() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 50);
+        let mut board = basic_board_with_snake(snake);
+        // Stacked hazard above, a single hazard to the left and food in a hazard to the right
+        board.hazards = vec![
+            Coord { x: 5, y: 6 },
+            Coord { x: 5, y: 6 },
+            Coord { x: 4, y: 5 },
+            Coord { x: 6, y: 5 },
+        ];
+        board.food = vec![Coord { x: 6, y: 5 }];
+
+        let sims = board.simulate_move(true);
+        let health_after = |mv: Movement| {
+            let (_, b) = sims.iter().find(|(m, _)| m[0].mv == mv).unwrap();
+            b.snakes[0].as_ref().unwrap().health
+        };
+        assert_eq!(health_after(Movement::Up), 49 - 2 * 14);
+        assert_eq!(health_after(Movement::Left), 49 - 14);
+        assert_eq!(health_after(Movement::Right), 100);
+    }
+
+    #[test]
+    fn test_hazard_display() {
+        let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }], 100);
+        let mut board = basic_board_with_snake(snake);
+        board.width = 3;
+        board.height = 2;
+        board.hazards = vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }, Coord { x: 2, y: 0 }];
+        board.food = vec![Coord { x: 0, y: 0 }];
+        let rendered = format!("{}", board);
+        let rows: Vec<&str> = rendered.lines().skip(2).take(2).collect();
+        assert_eq!(rows, vec!["| h |", "|fsb|"]);
+    }
+
+    #[test]
+    fn test_hazard_starvation() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 10);
+        let mut board = basic_board_with_snake(snake);
+        board.hazards = vec![Coord { x: 5, y: 6 }, Coord { x: 4, y: 5 }, Coord { x: 6, y: 5 }];
+
+        let after_ours = board.simulate_move(true)[0].1.clone();
+        let after_turn = after_ours.simulate_move(false)[0].1.clone();
+        assert!(after_turn.snakes[0].is_none());
+    }
+
+    #[test]
+    fn test_hazard_heuristic() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 100);
+        let mut board = basic_board_with_snake(snake);
+        board.snakes[2] = Some(create_snake_at(vec![Coord { x: 0, y: 0 }], 100));
+        let mut in_hazard = board.clone();
+        in_hazard.hazards = vec![Coord { x: 5, y: 5 }];
+        assert!(in_hazard.heuristic(true) < board.heuristic(true));
+
+        // Territory inside hazards is discounted
+        let area = vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }, Coord { x: 2, y: 0 }];
+        assert_eq!(board.territory_value(&area), 3);
+        in_hazard.hazards = vec![Coord { x: 1, y: 0 }, Coord { x: 2, y: 0 }];
+        assert_eq!(in_hazard.territory_value(&area), 2);
+    }
+
+    #[test]
+    fn test_stacked_hazard_territory() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 100);
+        let mut board = basic_board_with_snake(snake);
+        let area = vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }, Coord { x: 2, y: 0 }];
+        board.hazards = vec![Coord { x: 1, y: 0 }, Coord { x: 2, y: 0 }, Coord { x: 2, y: 0 }];
+        // A single hazard halves a cell, a stacked one takes all of it
+        assert_eq!(board.territory_value(&area), 1);
+        board.hazards = vec![Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }];
+        assert_eq!(board.territory_value(&area), 2);
+    }
+
+    fn constrictor_rules() -> SimpleRules {
+        SimpleRules {
+            mode: GameMode::Constrictor,
+            ..SimpleRules::default()
+        }
+    }
+
+    #[test]
+    fn test_constrictor_growth() {
+        let snake = create_snake_at(
+            vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }, Coord { x: 5, y: 3 }],
+            100,
+        );
+        let mut board = basic_board_with_snake(snake);
+        board.rules = constrictor_rules();
+        board.food = vec![Coord { x: 0, y: 0 }];
+
+        let first = board.simulate_move(true)[0].1.clone();
+        let second = first.simulate_move(true)[0].1.clone();
+        let snake = second.snakes[0].as_ref().unwrap();
+        assert_eq!(snake.health, 100);
+        assert_eq!(snake.body.len(), 5);
+        assert_eq!(snake.body.back(), Some(&Coord { x: 5, y: 4 }));
+        assert!(second.food.is_empty());
+    }
+
+    #[test]
+    fn test_constrictor_tails_are_walls() {
+        // Same position as test_team_tail, but tails never move
+        let snake = create_snake_at(
+            vec![
+                Coord { x: 1, y: 1 }, // head
+                Coord { x: 1, y: 2 }, // neck
+                Coord { x: 0, y: 2 },
+                Coord { x: 0, y: 1 },
+            ],
+            100,
+        );
+        let teammate = create_snake_at(
+            vec![
+                Coord { x: 3, y: 1 }, // head
+                Coord { x: 3, y: 2 }, // neck
+                Coord { x: 2, y: 2 },
+                Coord { x: 2, y: 1 },
+            ],
+            100,
+        );
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake.clone()), Some(teammate), None, None],
+            teams: vec![vec![0, 1], vec![2, 3]],
+            rules: constrictor_rules(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        let moves = snake.get_safe_moves(&board, true);
+        assert_eq!(moves, vec![Movement::Down]);
+        let moves = snake.get_safe_moves(&board, false);
+        assert_eq!(moves, vec![Movement::Down]);
+    }
+
+    #[test]
+    fn test_zobrist_transposition() {
+        // Up, Right, Up and Right, Up, Up end in the same position
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 100);
+        let board = basic_board_with_snake(snake);
+        let play = |moves: &[Movement]| {
+            moves.iter().fold(board.clone(), |b, mv| {
+                b.simulate_move(true)
+                    .into_iter()
+                    .find(|(m, _)| m[0].mv == *mv)
+                    .unwrap()
+                    .1
+            })
+        };
+        let a = play(&[Movement::Up, Movement::Right, Movement::Up]);
+        let b = play(&[Movement::Right, Movement::Up, Movement::Up]);
+        let c = play(&[Movement::Up, Movement::Up, Movement::Right]);
+        assert_eq!(a.zobrist_hash(true), b.zobrist_hash(true));
+        assert_ne!(a.zobrist_hash(true), c.zobrist_hash(true));
+        assert_ne!(a.zobrist_hash(true), a.zobrist_hash(false));
+    }
+
+    #[test]
+    fn test_zobrist_features() {
+        let snake = create_snake_at(vec![Coord { x: 5, y: 5 }, Coord { x: 5, y: 4 }], 100);
+        let board = basic_board_with_snake(snake);
+        let hash = board.zobrist_hash(true);
+
+        // A stacked tail cancels out in the body keys but not in the length
+        let mut stacked = board.clone();
+        let tail = stacked.snakes[0].as_ref().unwrap().body[1];
+        stacked.snakes[0].as_mut().unwrap().body.push_back(tail);
+        assert_ne!(stacked.zobrist_hash(true), hash);
+
+        let mut hungry = board.clone();
+        hungry.snakes[0].as_mut().unwrap().health = 50;
+        assert_ne!(hungry.zobrist_hash(true), hash);
+        // Every point of health counts
+        hungry.snakes[0].as_mut().unwrap().health = 5;
+        let starving = hungry.zobrist_hash(true);
+        hungry.snakes[0].as_mut().unwrap().health = 6;
+        assert_ne!(hungry.zobrist_hash(true), starving);
+
+        let mut fed = board.clone();
+        fed.food.push(Coord { x: 1, y: 1 });
+        assert_ne!(fed.zobrist_hash(true), hash);
+    }
+
+    #[test]
+    fn test_simulate_joint_head_to_head() {
+        // Both heads move into (5,5) at the same time, the shorter snake dies
+        let snake = create_snake_at(
+            vec![Coord { x: 4, y: 5 }, Coord { x: 3, y: 5 }, Coord { x: 2, y: 5 }],
+            100,
+        );
+        let enemy = create_snake_at(vec![Coord { x: 6, y: 5 }, Coord { x: 7, y: 5 }], 100);
+        let board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+
+        assert_eq!(board.side_moves(true).len(), 3);
+        assert_eq!(board.side_moves(false).len(), 3);
+        let next = board.simulate_joint(&[
+            SnakeMove { id: 0, mv: Movement::Right },
+            SnakeMove { id: 1, mv: Movement::Left },
+        ]);
+        assert_eq!(next.snakes[0].as_ref().unwrap().body[0], Coord { x: 5, y: 5 });
+        assert!(next.snakes[1].is_none());
+        assert_eq!(next.heuristic(true), i32::MAX);
+    }
+
+    #[test]
+    fn test_heuristic_weights() {
+        // We are one longer, only the length term separates the teams
+        let snake = create_snake_at(
+            vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }, Coord { x: 0, y: 0 }],
+            100,
+        );
+        let enemy = create_snake_at(vec![Coord { x: 9, y: 9 }, Coord { x: 9, y: 10 }], 100);
+        let mut board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        assert_eq!(board.heuristic(true), 8);
+
+        board.weights.length = 3;
+        board.stored_fast_heuristic.set(None);
+        assert_eq!(board.heuristic(true), 3);
+    }
+
+    #[test]
+    fn test_trapped_snake_scores_worse() {
+        // The enemy walls off the bottom left corner, its tail is far away
+        let enemy = create_snake_at(
+            [(4, 0), (3, 0), (3, 1), (3, 2), (3, 3), (2, 3), (1, 3), (0, 3), (0, 4), (0, 5), (0, 6), (0, 7), (0, 8)]
+                .iter()
+                .map(|&(x, y)| Coord { x, y })
+                .collect(),
+            100,
+        );
+        let trapped = create_snake_at(
+            vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }, Coord { x: 0, y: 0 }],
+            100,
+        );
+        // Same snake out in the open
+        let free = create_snake_at(
+            vec![Coord { x: 7, y: 7 }, Coord { x: 7, y: 6 }, Coord { x: 6, y: 6 }],
+            100,
+        );
+        let board = |snake: SimpleSnake| SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy.clone())],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        let trapped = board(trapped);
+        let free = board(free);
+
+        // Same fast heuristic, only the territory tells them apart
+        assert_eq!(trapped.heuristic(true), free.heuristic(true));
+        assert!(trapped.heuristic(false) < free.heuristic(false));
+        assert_eq!(trapped.flood_fill().area(0).len(), 9);
+
+        // The flood fill part is cached
+        let cached = trapped.stored_flood_fill_heuristic.get().unwrap();
+        assert_eq!(trapped.heuristic(false), trapped.heuristic(true) + cached);
+    }
+
+    #[test]
+    fn test_largest_area_move() {
+        // Left leads into a one cell dead end between the two bodies
+        let body = |cells: &[(i32, i32)]| cells.iter().map(|&(x, y)| Coord { x, y }).collect();
+        let snake = create_snake_at(body(&[(1, 3), (1, 2), (0, 2), (0, 1), (0, 0)]), 100);
+        let enemy = create_snake_at(
+            body(&[(3, 5), (3, 6), (2, 6), (2, 5), (2, 4), (1, 4), (0, 4), (0, 5), (0, 6), (1, 6)]),
+            100,
+        );
+        let board = SimpleBoard {
+            width: 7,
+            height: 7,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        let moves = board.largest_area_move();
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].mv, Movement::Right);
+    }
+
+    #[test]
+    fn test_flood_fill_bodies_vacate() {
+        // 2x2 board, the tail frees up after one turn and the neck after two
+        let snake = create_snake_at(
+            vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }, Coord { x: 0, y: 0 }],
+            100,
+        );
+        let mut board = basic_board_with_snake(snake);
+        board.width = 2;
+        board.height = 2;
+        let flood_fill = board.flood_fill();
+        assert_eq!(flood_fill.area(0).len(), 4);
+        let distances = &flood_fill.distances[&0];
+        assert_eq!(distances[board.cell_index(&Coord { x: 0, y: 1 })], Some(1));
+        assert_eq!(distances[board.cell_index(&Coord { x: 0, y: 0 })], Some(2));
+        assert_eq!(distances[board.cell_index(&Coord { x: 1, y: 0 })], Some(3));
+
+        // Constrictor bodies stay forever
+        board.rules = constrictor_rules();
+        let flood_fill = board.flood_fill();
+        assert_eq!(flood_fill.area(0).len(), 2);
+        assert_eq!(flood_fill.distances[&0][board.cell_index(&Coord { x: 1, y: 0 })], None);
+    }
+
+    #[test]
+    fn test_flood_fill_contested() {
+        // 5x1 board, the middle cell is as far from both heads
+        let snake = create_snake_at(vec![Coord { x: 0, y: 0 }], 100);
+        let enemy = create_snake_at(vec![Coord { x: 4, y: 0 }], 100);
+        let board = SimpleBoard {
+            width: 5,
+            height: 1,
+            food: vec![],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        let flood_fill = board.flood_fill();
+        assert_eq!(flood_fill.area(0), &[Coord { x: 0, y: 0 }, Coord { x: 1, y: 0 }]);
+        assert_eq!(flood_fill.area(1), &[Coord { x: 4, y: 0 }, Coord { x: 3, y: 0 }]);
+        assert_eq!(flood_fill.contested, vec![Coord { x: 2, y: 0 }]);
+        assert_eq!(flood_fill.distances[&0][2], Some(2));
+        assert_eq!(flood_fill.distances[&1][2], Some(2));
+        assert_eq!(flood_fill.distances[&0][3], None);
+    }
+
+    #[test]
+    fn test_food_distance() {
+        let enemy = create_snake_at(vec![Coord { x: 10, y: 10 }, Coord { x: 10, y: 9 }], 100);
+        let board = |health: i32, food: Coord| {
+            let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }], health);
+            SimpleBoard {
+                width: 11,
+                height: 11,
+                food: vec![food],
+                hazards: vec![],
+                snakes: vec![Some(snake), Some(enemy.clone())],
+                teams: vec![vec![0], vec![1]],
+                rules: SimpleRules::default(),
+                weights: HeuristicWeights::default(),
+                stored_fast_heuristic: std::cell::Cell::new(None),
+                stored_flood_fill_heuristic: std::cell::Cell::new(None),
+            }
+        };
+        let near = Coord { x: 2, y: 2 };
+        let far = Coord { x: 5, y: 4 };
+        // Both are closer to us than to the enemy
+        let gap = |health| board(health, near).heuristic(false) - board(health, far).heuristic(false);
+        assert!(gap(90) > 0);
+        // Food matters more the hungrier we are
+        assert!(gap(30) > gap(90));
+
+        // Food the enemy reaches first does not help us
+        let theirs = board(50, Coord { x: 9, y: 9 });
+        let flood_fill = theirs.flood_fill();
+        assert_eq!(flood_fill.distances[&0][theirs.cell_index(&Coord { x: 9, y: 9 })], None);
+    }
+
+    #[test]
+    fn test_starving() {
+        // 5 health left, the food is 7 moves away
+        let snake = create_snake_at(vec![Coord { x: 1, y: 1 }, Coord { x: 1, y: 0 }], 5);
+        let enemy = create_snake_at(vec![Coord { x: 10, y: 10 }, Coord { x: 10, y: 9 }], 100);
+        let mut board = SimpleBoard {
+            width: 11,
+            height: 11,
+            food: vec![Coord { x: 5, y: 4 }],
+            hazards: vec![],
+            snakes: vec![Some(snake), Some(enemy)],
+            teams: vec![vec![0], vec![1]],
+            rules: SimpleRules::default(),
+            weights: HeuristicWeights::default(),
+            stored_fast_heuristic: std::cell::Cell::new(None),
+            stored_flood_fill_heuristic: std::cell::Cell::new(None),
+        };
+        let starving = board.heuristic(false);
+        board.weights.starving = 0;
+        board.stored_flood_fill_heuristic.set(None);
+        assert_eq!(board.heuristic(false) - starving, HeuristicWeights::default().starving);
+    }
 }
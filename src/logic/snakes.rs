@@ -0,0 +1,150 @@
+// The snakes served by this server. The snake at / is configured with the
+// SNAKE_* environment variables, more snakes can be added with the JSON file
+// named by SNAKE_CONFIGS and are served under their own name, e.g. /alpha/move.
+// Each snake has its own appearance, engine, weights and team registry, so one
+// server can play both sides of a scrimmage.
+
+use crate::logic::engine::Engine;
+use crate::logic::teams::{TeamConfig, TeamRegistry};
+use crate::logic::weights::HeuristicWeights;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct Appearance {
+    pub author: String,
+    pub color: String,
+    pub head: String,
+    pub tail: String,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            author: String::from("Group 18"), // TODO: Your Battlesnake Username
+            color: String::from("#e83d84"),   // TODO: Choose color
+            head: String::from("tiger-king"), // TODO: Choose head
+            tail: String::from("coffee"),     // TODO: Choose tail
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct SnakeConfig {
+    #[serde(flatten)]
+    pub appearance: Appearance,
+    pub engine: Engine,
+    pub weights: HeuristicWeights,
+    pub teams: TeamConfig,
+}
+
+impl SnakeConfig {
+    // The snake at /
+    pub fn from_env() -> Result<Self, String> {
+        Ok(SnakeConfig {
+            appearance: Appearance::default(),
+            engine: Engine::from_env(),
+            weights: HeuristicWeights::from_env()?,
+            teams: TeamConfig::from_env()?,
+        })
+    }
+
+    // Snakes by name, e.g. {"alpha": {"color": "#ff0000", "engine": "mcts"}}.
+    // Fields left out keep their defaults, not the values of the snake at /
+    pub fn named_from_json(json: &str) -> Result<BTreeMap<String, Self>, String> {
+        let configs: BTreeMap<String, SnakeConfig> =
+            serde_json::from_str(json).map_err(|e| format!("invalid snake configs: {}", e))?;
+        // Names are path segments, /team/... is taken by the snake at /
+        for name in configs.keys() {
+            let segment = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if name.is_empty() || name == "team" || !segment {
+                return Err(format!("invalid snake name {:?}", name));
+            }
+        }
+        Ok(configs)
+    }
+
+    pub fn named_from_file(path: &str) -> Result<BTreeMap<String, Self>, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read snake configs from {}: {}", path, e))?;
+        SnakeConfig::named_from_json(&json)
+    }
+
+    // Snakes from the file in SNAKE_CONFIGS, none if it is not set
+    pub fn named_from_env() -> Result<BTreeMap<String, Self>, String> {
+        match std::env::var("SNAKE_CONFIGS") {
+            Ok(path) => SnakeConfig::named_from_file(&path),
+            Err(_) => Ok(BTreeMap::new()),
+        }
+    }
+}
+
+pub struct Snake {
+    pub config: SnakeConfig,
+    pub registry: Mutex<TeamRegistry>,
+}
+
+impl Snake {
+    pub fn new(config: SnakeConfig) -> Self {
+        Snake {
+            registry: Mutex::new(TeamRegistry::new(config.teams.clone())),
+            config,
+        }
+    }
+}
+
+// Snakes keyed by the path they are mounted at
+pub type Snakes = HashMap<String, Snake>;
+
+// The snake at / and every named snake at /<name>
+pub fn snakes(root: SnakeConfig, named: BTreeMap<String, SnakeConfig>) -> Snakes {
+    let mut snakes: Snakes = named
+        .into_iter()
+        .map(|(name, config)| (format!("/{}", name), Snake::new(config)))
+        .collect();
+    snakes.insert(String::from("/"), Snake::new(root));
+    snakes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{snakes, Appearance, SnakeConfig};
+    use crate::logic::engine::Engine;
+    use crate::logic::weights::HeuristicWeights;
+
+    #[test]
+    fn test_named_configs() {
+        let configs = SnakeConfig::named_from_json(
+            r##"{
+                "alpha": { "color": "#ff0000", "engine": "mcts", "weights": { "length": 3 } },
+                "beta": { "teams": { "names": { "Beta": "b" } } }
+            }"##,
+        )
+        .unwrap();
+        let alpha = &configs["alpha"];
+        assert_eq!(alpha.appearance.color, "#ff0000");
+        assert_eq!(alpha.appearance.head, Appearance::default().head);
+        assert_eq!(alpha.engine, Engine::Mcts);
+        assert_eq!(alpha.weights.length, 3);
+        assert_eq!(alpha.weights.danger, HeuristicWeights::default().danger);
+        let beta = &configs["beta"];
+        assert_eq!(beta.engine, Engine::Minimax);
+        assert_eq!(beta.teams.names["Beta"], "b");
+
+        let snakes = snakes(SnakeConfig::default(), configs);
+        let mut paths: Vec<&str> = snakes.keys().map(|p| p.as_str()).collect();
+        paths.sort();
+        assert_eq!(paths, vec!["/", "/alpha", "/beta"]);
+    }
+
+    #[test]
+    fn test_invalid_configs() {
+        assert!(SnakeConfig::named_from_json(r#"{ "team": {} }"#).is_err());
+        assert!(SnakeConfig::named_from_json(r#"{ "a/b": {} }"#).is_err());
+        assert!(SnakeConfig::named_from_json(r#"{ "alpha": { "engine": "alphabeta" } }"#).is_err());
+        assert!(SnakeConfig::named_from_file("/does/not/exist.json").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+// Which snakes play together. The team of a snake comes from the URL it was
+// called on (/team/<name>/move), the squad field in squad games, or the names
+// and colours in the JSON file named by SNAKE_TEAMS. Games are stored per
+// team, so one server can play on both sides of the same game. Every game has
+// its own lock, a search only blocks the requests of its own game and team.
+
+use crate::logic::engine::Engine;
+use crate::logic::weights::HeuristicWeights;
+use crate::{Battlesnake, GameInfo, GameMode, GameState, Ruleset};
+use log::warn;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct TeamConfig {
+    // Snake name to team
+    pub names: HashMap<String, String>,
+    // Snake colour to team, e.g. "#e83d84"
+    pub colors: HashMap<String, String>,
+}
+
+impl TeamConfig {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid team config: {}", e))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read team config from {}: {}", path, e))?;
+        TeamConfig::from_json(&json)
+    }
+
+    // Config from the file in SNAKE_TEAMS, no configured teams if it is not set
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("SNAKE_TEAMS") {
+            Ok(path) => TeamConfig::from_file(&path),
+            Err(_) => Ok(TeamConfig::default()),
+        }
+    }
+
+    // The team of a snake that did not name one in its URL. Without anything
+    // else to go on snakes with the same name are copies of one snake in a
+    // squad game, in every other game each snake plays alone
+    pub fn team_of(&self, snake: &Battlesnake, ruleset: &Ruleset) -> String {
+        if !snake.squad.is_empty() {
+            return snake.squad.clone();
+        }
+        // Colours are compared without case
+        let color = &snake.customizations.color;
+        let by_color = self
+            .colors
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(color))
+            .map(|(_, team)| team);
+        if let Some(team) = self.names.get(&snake.name).or(by_color) {
+            return team.clone();
+        }
+        if ruleset.mode() == GameMode::Squad {
+            return snake.name.clone();
+        }
+        snake.id.clone()
+    }
+}
+
+pub type SharedGame = Arc<Mutex<GameInfo>>;
+
+pub struct TeamRegistry {
+    config: TeamConfig,
+    // Keyed by game ID and team
+    games: HashMap<(String, String), SharedGame>,
+}
+
+impl TeamRegistry {
+    pub fn new(config: TeamConfig) -> Self {
+        TeamRegistry {
+            config,
+            games: HashMap::new(),
+        }
+    }
+
+    // The team the snake of the request plays in, its URL wins over everything else
+    pub fn team(&self, state: &GameState, path_team: Option<&str>) -> String {
+        match path_team {
+            Some(team) => team.to_string(),
+            None => self.config.team_of(&state.you, &state.game.ruleset),
+        }
+    }
+
+    // Our snake first, then the other snakes on the board in the team
+    pub fn squad_of<'a>(&self, state: &'a GameState, team: &str) -> Vec<&'a str> {
+        let mut squad = vec![state.you.id.as_str()];
+        squad.extend(
+            state
+                .board
+                .snakes
+                .iter()
+                .filter(|s| s.id != state.you.id && self.config.team_of(s, &state.game.ruleset) == team)
+                .map(|s| s.id.as_str()),
+        );
+        squad
+    }
+
+    // The game of our team. A game that was never started here, e.g. after a
+    // restart, is rebuilt from the request with the snakes of the team on the
+    // board and plays with the given weights and engine. The game is not
+    // locked here, a snake that joins an existing game is registered by the
+    // caller once it holds the lock of the game
+    pub fn game(
+        &mut self,
+        state: &GameState,
+        path_team: Option<&str>,
+        weights: HeuristicWeights,
+        engine: Engine,
+    ) -> SharedGame {
+        let team = self.team(state, path_team);
+        let key = (state.game.id.clone(), team.clone());
+        if !self.games.contains_key(&key) {
+            if state.turn > 0 {
+                warn!("Game {} was not started here, rebuilding it from turn {}", state.game.id, state.turn);
+            }
+            let mut game_info = GameInfo::new(&state.game, weights, engine);
+            for id in self.squad_of(state, &team) {
+                game_info.add_agent(id, state.turn);
+            }
+            self.games.insert(key.clone(), Arc::new(Mutex::new(game_info)));
+        }
+        self.games[&key].clone()
+    }
+
+    pub fn end(&mut self, state: &GameState, path_team: Option<&str>) -> Option<SharedGame> {
+        let team = self.team(state, path_team);
+        self.games.remove(&(state.game.id.clone(), team))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TeamConfig, TeamRegistry};
+    use crate::logic::engine::Engine;
+    use crate::logic::weights::HeuristicWeights;
+    use crate::GameState;
+    use serde_json::{json, Value};
+
+    fn snake(id: &str, name: &str, squad: &str, color: &str) -> Value {
+        json!({
+            "id": id,
+            "name": name,
+            "health": 90,
+            "head": { "x": 0, "y": 0 },
+            "length": 1,
+            "body": [{ "x": 0, "y": 0 }],
+            "latency": "",
+            "shout": null,
+            "squad": squad,
+            "customizations": { "color": color, "head": "default", "tail": "default" },
+        })
+    }
+
+    // The first snake is the one the request is for
+    fn state(ruleset: &str, snakes: Vec<Value>) -> GameState {
+        serde_json::from_value(json!({
+            "game": { "id": "game", "ruleset": { "name": ruleset }, "timeout": 100 },
+            "turn": 0,
+            "board": { "height": 7, "width": 7, "food": [], "hazards": [], "snakes": snakes },
+            "you": snakes[0],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_name_convention() {
+        let registry = TeamRegistry::new(TeamConfig::default());
+        let snakes = vec![
+            snake("us", "copy", "", "#888888"),
+            snake("friend", "copy", "", "#888888"),
+            snake("them", "other", "", "#888888"),
+        ];
+        // Copies of a snake only play together in squad games
+        let standard = state("standard", snakes.clone());
+        assert_eq!(registry.squad_of(&standard, &registry.team(&standard, None)), vec!["us"]);
+        let squad = state("squad", snakes);
+        assert_eq!(registry.squad_of(&squad, &registry.team(&squad, None)), vec!["us", "friend"]);
+    }
+
+    #[test]
+    fn test_squad_field() {
+        // The squad field wins over names
+        let registry = TeamRegistry::new(TeamConfig::default());
+        let state = state(
+            "squad",
+            vec![
+                snake("us", "a", "red", "#888888"),
+                snake("copy", "a", "blue", "#888888"),
+                snake("friend", "b", "red", "#888888"),
+            ],
+        );
+        assert_eq!(registry.team(&state, None), "red");
+        assert_eq!(registry.squad_of(&state, "red"), vec!["us", "friend"]);
+    }
+
+    #[test]
+    fn test_configured_teams() {
+        let config = TeamConfig::from_json(
+            r##"{ "names": { "Alice": "left" }, "colors": { "#FF0000": "left", "#0000ff": "right" } }"##,
+        )
+        .unwrap();
+        let registry = TeamRegistry::new(config);
+        let state = state(
+            "standard",
+            vec![
+                snake("a", "Alice", "", "#123456"),
+                snake("b", "Bob", "", "#ff0000"),
+                snake("c", "Carol", "", "#0000FF"),
+                snake("d", "Dave", "", "#888888"),
+            ],
+        );
+        assert_eq!(registry.team(&state, None), "left");
+        assert_eq!(registry.squad_of(&state, "left"), vec!["a", "b"]);
+        let ruleset = &state.game.ruleset;
+        assert_eq!(registry.config.team_of(&state.board.snakes[2], ruleset), "right");
+        // Nobody else plays with Dave
+        assert_eq!(registry.config.team_of(&state.board.snakes[3], ruleset), "d");
+        assert!(TeamConfig::from_json(r#"{ "names": [] }"#).is_err());
+    }
+
+    #[test]
+    fn test_games_per_team() {
+        // One server playing both sides of a game through the URL
+        let mut registry = TeamRegistry::new(TeamConfig::default());
+        let us = state("standard", vec![snake("a", "snake", "", "#888888"), snake("b", "snake", "", "#888888")]);
+        let them = state("standard", vec![snake("b", "snake", "", "#888888"), snake("a", "snake", "", "#888888")]);
+        let (weights, engine) = (HeuristicWeights::default(), Engine::Minimax);
+        let left = registry.game(&us, Some("left"), weights, engine);
+        registry.game(&them, Some("right"), weights, engine);
+        assert_eq!(registry.games.len(), 2);
+        assert_eq!(left.lock().unwrap().agent_ids, vec!["a"]);
+        let right = registry.game(&them, Some("right"), weights, engine);
+        assert_eq!(right.lock().unwrap().agent_ids, vec!["b"]);
+
+        // A search on one side holds the lock of its own game only
+        let searching = left.lock().unwrap();
+        assert!(registry.game(&us, Some("left"), weights, engine).try_lock().is_err());
+        assert!(registry.game(&them, Some("right"), weights, engine).try_lock().is_ok());
+        drop(searching);
+
+        assert!(registry.end(&us, Some("left")).is_some());
+        assert!(registry.end(&us, Some("left")).is_none());
+        assert_eq!(registry.games.len(), 1);
+    }
+}
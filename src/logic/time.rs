@@ -0,0 +1,160 @@
+// Time budget of one move. The search works against an absolute deadline,
+// all arithmetic is done on Durations so long timeouts cannot overflow.
+
+use log::warn;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Kept free even when the network looks fast
+const SAFETY_MARGIN: Duration = Duration::from_millis(10);
+// Network overhead assumed before any latency has been measured
+const DEFAULT_NETWORK: Duration = Duration::from_millis(25);
+// Number of recent turns the network estimate is based on
+const LATENCY_WINDOW: usize = 5;
+// A round trip above this share of the timeout is logged as a near miss
+const DANGER_RATIO: f64 = 0.9;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    start: Instant,
+    deadline: Instant,
+}
+
+impl TimeManager {
+    // A budget of timeout minus margin, starting when the request arrived so
+    // time spent waiting for a lock counts against it
+    pub fn new(start: Instant, timeout: Duration, margin: Duration) -> Self {
+        TimeManager {
+            start,
+            deadline: start + timeout.saturating_sub(margin),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    pub fn expired(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+}
+
+// Latency history of one agent in a game. The latency the engine reports
+// for our previous move is the time we spent answering plus the network,
+// the difference is the network overhead we have to leave room for.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyTracker {
+    // Network overhead of recent turns, newest last
+    network: VecDeque<Duration>,
+    // Time spent answering the previous move
+    last_answer: Option<Duration>,
+}
+
+impl LatencyTracker {
+    // Called with the latency reported for the previous move
+    pub fn observe(&mut self, latency: &str, timeout: Duration, turn: i32) {
+        let (Some(latency), Some(answer)) = (parse_latency(latency), self.last_answer.take()) else {
+            return;
+        };
+        if latency.as_secs_f64() > timeout.as_secs_f64() * DANGER_RATIO {
+            warn!(
+                "Move {} took {:?} of the {:?} timeout, {:?} spent searching",
+                turn - 1,
+                latency,
+                timeout,
+                answer
+            );
+        }
+        self.network.push_back(latency.saturating_sub(answer));
+        if self.network.len() > LATENCY_WINDOW {
+            self.network.pop_front();
+        }
+    }
+
+    pub fn answered(&mut self, elapsed: Duration) {
+        self.last_answer = Some(elapsed);
+    }
+
+    // The slowest recent turn, so a slow turn shrinks the budget until it
+    // falls out of the window and the budget grows back
+    pub fn margin(&self) -> Duration {
+        self.network.iter().max().copied().unwrap_or(DEFAULT_NETWORK) + SAFETY_MARGIN
+    }
+}
+
+// Latency is sent as milliseconds in a string, empty on the first turn
+pub fn parse_latency(latency: &str) -> Option<Duration> {
+    latency.trim().parse::<u64>().ok().map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_latency, LatencyTracker, TimeManager, DEFAULT_NETWORK, SAFETY_MARGIN};
+    use std::time::{Duration, Instant};
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_parse_latency() {
+        assert_eq!(parse_latency("123"), Some(ms(123)));
+        assert_eq!(parse_latency(""), None);
+        assert_eq!(parse_latency("slow"), None);
+    }
+
+    #[test]
+    fn test_long_timeout() {
+        // Timeouts above i32::MAX nanoseconds used to overflow
+        let time = TimeManager::new(Instant::now(), ms(5000), LatencyTracker::default().margin());
+        assert!(!time.expired());
+        assert!(time.remaining() > ms(4000));
+        assert_eq!(time.deadline - time.start, ms(5000) - DEFAULT_NETWORK - SAFETY_MARGIN);
+
+        let time = TimeManager::new(Instant::now(), Duration::from_secs(3600), Duration::ZERO);
+        assert!(time.remaining() > Duration::from_secs(3599));
+    }
+
+    #[test]
+    fn test_short_timeout() {
+        let time = TimeManager::new(Instant::now(), ms(5), LatencyTracker::default().margin());
+        assert!(time.expired());
+        assert_eq!(time.remaining(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_latency_adapts() {
+        let mut tracker = LatencyTracker::default();
+        // Nothing to compare the first latency with
+        tracker.observe("", ms(500), 0);
+        assert_eq!(tracker.margin(), DEFAULT_NETWORK + SAFETY_MARGIN);
+
+        tracker.answered(ms(450));
+        tracker.observe("470", ms(500), 1);
+        assert_eq!(tracker.margin(), ms(20) + SAFETY_MARGIN);
+
+        // A slow turn shrinks the budget
+        tracker.answered(ms(450));
+        tracker.observe("530", ms(500), 2);
+        assert_eq!(tracker.margin(), ms(80) + SAFETY_MARGIN);
+
+        // and it grows back once the slow turn is out of the window
+        for turn in 3..8 {
+            tracker.answered(ms(400));
+            tracker.observe("410", ms(500), turn);
+        }
+        assert_eq!(tracker.margin(), ms(10) + SAFETY_MARGIN);
+    }
+
+    #[test]
+    fn test_latency_needs_an_answer() {
+        // A latency without a recorded answer is not trusted
+        let mut tracker = LatencyTracker::default();
+        tracker.observe("300", ms(500), 5);
+        assert_eq!(tracker.margin(), DEFAULT_NETWORK + SAFETY_MARGIN);
+    }
+}
@@ -0,0 +1,94 @@
+// Bounded transposition table for the minimax search, indexed by the
+// Zobrist hash of a SimpleBoard. Each slot keeps one entry, a new entry
+// replaces the old one unless the old one was searched deeper for the same
+// position.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    // The value is exact
+    Exact,
+    // The search failed high, the real value is at least this
+    Lower,
+    // The search failed low, the real value is at most this
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TTEntry {
+    pub key: u64,
+    pub value: i32,
+    // Plies searched below the position
+    pub depth: i32,
+    // How many plies below the position the value was reached
+    pub reach: i32,
+    pub bound: Bound,
+    // Key of the best joint move found, searched first next time
+    pub best: Option<u64>,
+}
+
+pub struct TranspositionTable {
+    entries: Vec<Option<TTEntry>>,
+    mask: usize,
+}
+
+impl TranspositionTable {
+    // The table holds 2^size_log2 entries
+    pub fn new(size_log2: u32) -> Self {
+        let size = 1usize << size_log2;
+        TranspositionTable {
+            entries: vec![None; size],
+            mask: size - 1,
+        }
+    }
+
+    pub fn get(&self, key: u64) -> Option<TTEntry> {
+        self.entries[key as usize & self.mask].filter(|e| e.key == key)
+    }
+
+    pub fn store(&mut self, entry: TTEntry) {
+        let slot = &mut self.entries[entry.key as usize & self.mask];
+        match slot {
+            Some(old) if old.key == entry.key && old.depth > entry.depth => {}
+            _ => *slot = Some(entry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bound, TTEntry, TranspositionTable};
+
+    fn entry(key: u64, depth: i32) -> TTEntry {
+        TTEntry {
+            key,
+            value: depth * 10,
+            depth,
+            reach: depth,
+            bound: Bound::Exact,
+            best: None,
+        }
+    }
+
+    #[test]
+    fn test_store_and_get() {
+        let mut tt = TranspositionTable::new(4);
+        tt.store(entry(3, 2));
+        assert_eq!(tt.get(3), Some(entry(3, 2)));
+        assert_eq!(tt.get(4), None);
+        // Same slot, different position
+        assert_eq!(tt.get(3 + 16), None);
+    }
+
+    #[test]
+    fn test_replacement() {
+        let mut tt = TranspositionTable::new(4);
+        tt.store(entry(3, 4));
+        // A shallower search of the same position does not replace
+        tt.store(entry(3, 2));
+        assert_eq!(tt.get(3).unwrap().depth, 4);
+        // Another position in the same slot always does
+        tt.store(entry(3 + 16, 1));
+        assert_eq!(tt.get(3), None);
+        assert_eq!(tt.get(3 + 16).unwrap().depth, 1);
+    }
+}
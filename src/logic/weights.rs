@@ -0,0 +1,136 @@
+// Weights of the heuristic terms. They are loaded from the JSON file named by
+// SNAKE_WEIGHTS at startup, missing fields keep their defaults, and can be
+// overridden with query parameters on /start and /move for experiments.
+
+use rocket::FromForm;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct HeuristicWeights {
+    // Per point of health below health_threshold
+    pub health: i32,
+    pub health_threshold: i32,
+    // Per body segment
+    pub length: i32,
+    // Per snake alive
+    pub alive: i32,
+    // Per point of hazard damage taken by standing still
+    pub hazard: i32,
+    // Per cell of reachable territory
+    pub territory: i32,
+    // Per body segment that does not fit in the reachable territory
+    pub danger: i32,
+    // Per turn to the nearest food we reach first and 10 points of missing health
+    pub food: i32,
+    // Per snake that cannot reach food before it starves
+    pub starving: i32,
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> Self {
+        HeuristicWeights {
+            health: 1,
+            health_threshold: 20,
+            length: 8,
+            alive: 20,
+            hazard: 1,
+            territory: 1,
+            danger: 4,
+            food: 1,
+            starving: 40,
+        }
+    }
+}
+
+impl HeuristicWeights {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| format!("invalid heuristic weights: {}", e))
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read heuristic weights from {}: {}", path, e))?;
+        HeuristicWeights::from_json(&json)
+    }
+
+    // Weights from the file in SNAKE_WEIGHTS, defaults if it is not set
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("SNAKE_WEIGHTS") {
+            Ok(path) => HeuristicWeights::from_file(&path),
+            Err(_) => Ok(HeuristicWeights::default()),
+        }
+    }
+}
+
+// Query parameters replacing single weights, e.g. /move?length=10&danger=2
+#[derive(FromForm, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WeightOverrides {
+    health: Option<i32>,
+    health_threshold: Option<i32>,
+    length: Option<i32>,
+    alive: Option<i32>,
+    hazard: Option<i32>,
+    territory: Option<i32>,
+    danger: Option<i32>,
+    food: Option<i32>,
+    starving: Option<i32>,
+}
+
+impl WeightOverrides {
+    pub fn apply(&self, weights: HeuristicWeights) -> HeuristicWeights {
+        HeuristicWeights {
+            health: self.health.unwrap_or(weights.health),
+            health_threshold: self.health_threshold.unwrap_or(weights.health_threshold),
+            length: self.length.unwrap_or(weights.length),
+            alive: self.alive.unwrap_or(weights.alive),
+            hazard: self.hazard.unwrap_or(weights.hazard),
+            territory: self.territory.unwrap_or(weights.territory),
+            danger: self.danger.unwrap_or(weights.danger),
+            food: self.food.unwrap_or(weights.food),
+            starving: self.starving.unwrap_or(weights.starving),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeuristicWeights, WeightOverrides};
+
+    #[test]
+    fn test_partial_weights() {
+        let weights = HeuristicWeights::from_json(r#"{"length": 12, "danger": 0}"#).unwrap();
+        assert_eq!(
+            weights,
+            HeuristicWeights {
+                length: 12,
+                danger: 0,
+                ..HeuristicWeights::default()
+            }
+        );
+        assert!(HeuristicWeights::from_json(r#"{"length": "long"}"#).is_err());
+    }
+
+    #[test]
+    fn test_weights_file() {
+        let path = std::env::temp_dir().join("snake_weights_test.json");
+        std::fs::write(&path, r#"{"alive": 50}"#).unwrap();
+        let weights = HeuristicWeights::from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(weights.alive, 50);
+        assert_eq!(weights.length, HeuristicWeights::default().length);
+        assert!(HeuristicWeights::from_file("/does/not/exist.json").is_err());
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = WeightOverrides {
+            territory: Some(3),
+            ..WeightOverrides::default()
+        };
+        let weights = overrides.apply(HeuristicWeights::default());
+        assert_eq!(weights.territory, 3);
+        assert_eq!(weights.length, 8);
+        assert_eq!(WeightOverrides::default().apply(weights), weights);
+    }
+}
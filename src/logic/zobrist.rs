@@ -0,0 +1,115 @@
+// Zobrist keys for hashing SimpleBoard positions, see
+// https://www.chessprogramming.org/Zobrist_Hashing
+//
+// Every feature of a position (a body segment of a snake on a cell, a head,
+// a length, a health, a food and the side to move) has a random key, the hash
+// of a position is the xor of the keys of its features.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::OnceLock;
+
+// Large enough for 16 snakes on a 32x32 board, bigger games can not be
+// hashed and are not searched with a transposition table
+pub const MAX_SNAKES: usize = 16;
+pub const MAX_CELLS: usize = 32 * 32;
+// Health is hashed exactly, the heuristic weighs every point of it
+const MAX_HEALTH: i32 = 100;
+
+struct Keys {
+    body: Vec<u64>,
+    head: Vec<u64>,
+    length: Vec<u64>,
+    health: Vec<u64>,
+    food: Vec<u64>,
+    our_turn: u64,
+}
+
+fn keys() -> &'static Keys {
+    static KEYS: OnceLock<Keys> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        // Fixed seed so hashes are the same between runs
+        let mut rng = StdRng::seed_from_u64(0x5eed_5a4e);
+        let mut table = |n: usize| (0..n).map(|_| rng.gen::<u64>()).collect::<Vec<u64>>();
+        Keys {
+            body: table(MAX_SNAKES * MAX_CELLS),
+            head: table(MAX_SNAKES * MAX_CELLS),
+            length: table(MAX_SNAKES * (MAX_CELLS + 1)),
+            health: table(MAX_SNAKES * (MAX_HEALTH as usize + 1)),
+            food: table(MAX_CELLS),
+            our_turn: rng.gen::<u64>(),
+        }
+    })
+}
+
+// Whether positions with this many snakes and cells can be hashed
+pub fn fits(snakes: usize, cells: usize) -> bool {
+    snakes <= MAX_SNAKES && cells <= MAX_CELLS
+}
+
+// Keys of larger games would be shared between snakes or cells, check fits first
+fn cell_index(snake: usize, cell: usize) -> usize {
+    assert!(fits(snake + 1, cell + 1), "no Zobrist key for snake {} on cell {}", snake, cell);
+    snake * MAX_CELLS + cell
+}
+
+pub fn body(snake: usize, cell: usize) -> u64 {
+    keys().body[cell_index(snake, cell)]
+}
+
+pub fn head(snake: usize, cell: usize) -> u64 {
+    keys().head[cell_index(snake, cell)]
+}
+
+// Stacked tails cancel out in the body keys, the length tells them apart
+pub fn length(snake: usize, length: usize) -> u64 {
+    assert!(snake < MAX_SNAKES, "no Zobrist key for snake {}", snake);
+    keys().length[snake * (MAX_CELLS + 1) + length.min(MAX_CELLS)]
+}
+
+pub fn health(snake: usize, health: i32) -> u64 {
+    assert!(snake < MAX_SNAKES, "no Zobrist key for snake {}", snake);
+    keys().health[snake * (MAX_HEALTH as usize + 1) + health.clamp(0, MAX_HEALTH) as usize]
+}
+
+pub fn food(cell: usize) -> u64 {
+    assert!(cell < MAX_CELLS, "no Zobrist key for cell {}", cell);
+    keys().food[cell]
+}
+
+pub fn our_turn() -> u64 {
+    keys().our_turn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{body, fits, food, health, our_turn, MAX_CELLS, MAX_SNAKES};
+
+    #[test]
+    fn test_keys_are_stable_and_distinct() {
+        assert_eq!(body(0, 5), body(0, 5));
+        assert_ne!(body(0, 5), body(1, 5));
+        assert_ne!(body(0, 5), body(0, 6));
+        assert_ne!(food(5), body(0, 5));
+        assert_ne!(our_turn(), 0);
+    }
+
+    #[test]
+    fn test_exact_health() {
+        assert_ne!(health(0, 1), health(0, 9));
+        assert_ne!(health(0, 11), health(0, 19));
+        assert_ne!(health(0, 100), health(1, 100));
+        assert_eq!(health(0, -5), health(0, 0));
+    }
+
+    #[test]
+    fn test_large_games() {
+        assert!(fits(MAX_SNAKES, 25 * 25));
+        assert!(!fits(MAX_SNAKES + 1, 11 * 11));
+        assert!(!fits(2, 33 * 33));
+        // Snakes and cells past the tables never share a key with smaller ones
+        assert_ne!(body(0, MAX_CELLS - 1), body(1, 0));
+        assert!(std::panic::catch_unwind(|| body(MAX_SNAKES, 0)).is_err());
+        assert!(std::panic::catch_unwind(|| body(0, MAX_CELLS)).is_err());
+    }
+}
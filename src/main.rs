@@ -7,15 +7,16 @@ use rocket::http::Status;
 use rocket::serde::{json::Json, Deserialize};
 use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
 use std::env;
-use rocket::{get, launch, routes, State};
-use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use rocket::{get, launch, routes, Build, Rocket, Route, State};
 
 mod logic;
+use logic::engine::Engine;
 use logic::simple::Movement;
-
-type SharedData = Arc<Mutex<HashMap<String, GameInfo>>>;
+use logic::snakes::{Snake, SnakeConfig, Snakes};
+use logic::time::LatencyTracker;
+use logic::weights::{HeuristicWeights, WeightOverrides};
 
 // API and Response Objects
 // See https://docs.battlesnake.com/api
@@ -23,37 +24,134 @@ type SharedData = Arc<Mutex<HashMap<String, GameInfo>>>;
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Game {
     id: String,
-    ruleset: HashMap<String, Value>,
+    ruleset: Ruleset,
+    // Name of the map the board was set up with, e.g. "standard" or "royale"
+    #[serde(default)]
+    map: String,
     timeout: u32,
+    // Where the game was started, e.g. "league", "tournament" or "custom"
+    #[serde(default)]
+    source: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Ruleset {
+    name: String,
+    version: String,
+    settings: RulesetSettings,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RulesetSettings {
+    food_spawn_chance: u32,
+    minimum_food: u32,
+    hazard_damage_per_turn: i32,
+    hazard_map: String,
+    hazard_map_author: String,
+    royale: RoyaleSettings,
+    squad: SquadSettings,
+}
+
+// Defaults of the official standard ruleset
+impl Default for RulesetSettings {
+    fn default() -> Self {
+        RulesetSettings {
+            food_spawn_chance: 15,
+            minimum_food: 1,
+            hazard_damage_per_turn: 14,
+            hazard_map: String::new(),
+            hazard_map_author: String::new(),
+            royale: RoyaleSettings::default(),
+            squad: SquadSettings::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RoyaleSettings {
+    shrink_every_n_turns: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SquadSettings {
+    allow_body_collisions: bool,
+    shared_elimination: bool,
+    shared_health: bool,
+    shared_length: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    Standard,
+    Royale,
+    Constrictor,
+    Wrapped,
+    Solo,
+    Squad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topology {
+    Bounded,
+    Wrapped,
+}
+
+impl Ruleset {
+    pub fn topology(&self) -> Topology {
+        if self.name.contains("wrapped") {
+            Topology::Wrapped
+        } else {
+            Topology::Bounded
+        }
+    }
+
+    pub fn mode(&self) -> GameMode {
+        match self.name.as_str() {
+            "royale" => GameMode::Royale,
+            "constrictor" => GameMode::Constrictor,
+            "wrapped" => GameMode::Wrapped,
+            "solo" => GameMode::Solo,
+            "squad" => GameMode::Squad,
+            _ => GameMode::Standard,
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Board {
-    height: u32,
+    height: i32,
     width: i32,
+    #[serde(default)]
     food: Vec<Coord>,
     snakes: Vec<Battlesnake>,
+    #[serde(default)]
     hazards: Vec<Coord>,
 }
 
 impl std::fmt::Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         /* build board representation string */
-        let mut board: String = "\n|:---------:|".to_owned();
+        let border = format!("|:{}:|", "-".repeat((self.width - 2).max(0) as usize));
+        let mut board: String = format!("\n{}", border);
         for y in (0..self.height).rev() {
             board += "\n|";
             for x in 0..self.width {
-                let coord = Coord { x: x as i32, y: y as i32 };
-                let piece: String = if self.food.contains(&coord) {
-                    "f".to_string()
-                } else if self.hazards.contains(&coord) {
-                    "b".to_string()
-                } else if let Some(snake) = self.snakes.iter().find(|s| s.body.contains(&coord)) {
+                let coord = Coord { x, y };
+                // Snakes are drawn over hazards, they can stand in them
+                let piece: String = if let Some(snake) = self.snakes.iter().find(|s| s.body.contains(&coord)) {
                     if snake.body[0] == coord {
                         "h".to_string()
                     } else {
                         "s".to_string()
                     }
+                } else if self.food.contains(&coord) {
+                    "f".to_string()
+                } else if self.hazards.contains(&coord) {
+                    "b".to_string()
                 } else {
                     " ".to_string()
                 };
@@ -61,7 +159,7 @@ impl std::fmt::Display for Board {
             }
             board += "|";
         }
-        board += "\n|:---------:|";
+        board += &format!("\n{}", border);
 
         write!(f, "{}", board)
     }
@@ -75,8 +173,24 @@ pub struct Battlesnake {
     body: Vec<Coord>,
     head: Coord,
     length: i32,
+    // Milliseconds our previous move took as seen by the engine, empty on the first turn
+    #[serde(default)]
     latency: String,
+    #[serde(default)]
     shout: Option<String>,
+    // Empty outside of squad games
+    #[serde(default)]
+    squad: String,
+    #[serde(default)]
+    customizations: Customizations,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Customizations {
+    color: String,
+    head: String,
+    tail: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -99,40 +213,92 @@ pub struct GameState {
     you: Battlesnake,
 }
 
+#[derive(Clone)]
 pub struct GameInfo {
+    #[allow(dead_code)]
     id: String,
     timeout: u32,
-    agent_ids: [String; 2],
-    agent_moves: [Vec<Movement>; 2],
+    ruleset: Ruleset,
+    agent_ids: Vec<String>,
+    agent_moves: Vec<Vec<Movement>>,
+    agent_latency: Vec<LatencyTracker>,
+    weights: HeuristicWeights,
+    engine: Engine,
 }
 
-#[get("/")]
-fn handle_index() -> Json<Value> {
-    Json(logic::info())
-}
-
-#[post("/start", format = "json", data = "<start_req>")]
-fn handle_start(shared_data: &State<SharedData>, start_req: Json<GameState>) -> Status {
-    // Store game information in shared data
-    let mut data = shared_data.lock().unwrap();
-    // Check if the game ID already exists
-    let game_id = start_req.game.id.clone();
-    let you_id = start_req.you.id.clone();
-    if data.contains_key(&game_id) {
-        // Add agent ID to the existing game info
-        if let Some(game_info) = data.get_mut(&start_req.game.id) {
-            game_info.agent_ids[1] = you_id.clone();
+impl GameInfo {
+    fn new(game: &Game, weights: HeuristicWeights, engine: Engine) -> Self {
+        GameInfo {
+            id: game.id.clone(),
+            timeout: game.timeout,
+            ruleset: game.ruleset.clone(),
+            agent_ids: vec![],
+            agent_moves: vec![],
+            agent_latency: vec![],
+            weights,
+            engine,
+        }
+    }
+
+    // Registers one of our snakes. An agent that joins late gets placeholders
+    // for the turns it missed so its moves line up with the turn numbers
+    fn add_agent(&mut self, id: &str, turn: i32) {
+        if self.agent_ids.iter().any(|a| a == id) {
+            return;
         }
-    } else {
-        // Create a new game info entry
-        let game_info = GameInfo {
-            id: game_id.clone(),
-            timeout: start_req.game.timeout-25,
-            agent_ids: [you_id.clone(), String::new()],
-            agent_moves: [vec![], vec![]],
-        };
-        data.insert(game_id.clone(), game_info);
+        self.agent_ids.push(id.to_string());
+        self.agent_moves.push(vec![Movement::None; turn.max(0) as usize]);
+        self.agent_latency.push(LatencyTracker::default());
     }
+}
+
+// The snake a request was sent to, every snake's routes are mounted at its own path
+fn served<'a>(snakes: &'a Snakes, route: &Route) -> &'a Snake {
+    &snakes[route.uri.base()]
+}
+
+#[get("/")]
+fn handle_index(snakes: &State<Snakes>, route: &Route) -> Json<Value> {
+    Json(logic::info(&served(snakes, route).config.appearance))
+}
+
+#[get("/team/<_team>")]
+fn handle_team_index(snakes: &State<Snakes>, route: &Route, _team: &str) -> Json<Value> {
+    Json(logic::info(&served(snakes, route).config.appearance))
+}
+
+#[post("/start?<overrides..>", format = "json", data = "<start_req>")]
+fn handle_start(
+    snakes: &State<Snakes>,
+    route: &Route,
+    overrides: WeightOverrides,
+    start_req: Json<GameState>,
+) -> Status {
+    start_game(served(snakes, route), None, overrides, &start_req)
+}
+
+#[post("/team/<team>/start?<overrides..>", format = "json", data = "<start_req>")]
+fn handle_team_start(
+    snakes: &State<Snakes>,
+    route: &Route,
+    team: &str,
+    overrides: WeightOverrides,
+    start_req: Json<GameState>,
+) -> Status {
+    start_game(served(snakes, route), Some(team), overrides, &start_req)
+}
+
+fn start_game(
+    snake: &Snake,
+    team: Option<&str>,
+    overrides: WeightOverrides,
+    start_req: &GameState,
+) -> Status {
+    // Store game information in shared data, every agent of our team in the
+    // game is added to the same entry
+    let weights = overrides.apply(snake.config.weights);
+    let game = snake.registry.lock().unwrap().game(start_req, team, weights, snake.config.engine);
+    game.lock().unwrap().add_agent(&start_req.you.id, start_req.turn);
     logic::start(
         &start_req.game,
         &start_req.turn,
@@ -143,40 +309,107 @@ fn handle_start(shared_data: &State<SharedData>, start_req: Json<GameState>) ->
     Status::Ok
 }
 
-#[post("/move", format = "json", data = "<move_req>")]
-fn handle_move(shared_data: &State<SharedData>, move_req: Json<GameState>) -> Json<Value> {
-    // Retrieve game information from shared data
-    let mut data = shared_data.lock().unwrap();
-    let game_id = move_req.game.id.clone();
-    let game_info = data.get_mut(&game_id).unwrap_or_else(|| {
-        panic!("Game ID {} not found in shared data", game_id)
-    });
+#[post("/move?<overrides..>", format = "json", data = "<move_req>")]
+fn handle_move(
+    snakes: &State<Snakes>,
+    route: &Route,
+    overrides: WeightOverrides,
+    move_req: Json<GameState>,
+) -> Json<Value> {
+    move_game(served(snakes, route), None, overrides, &move_req)
+}
+
+#[post("/team/<team>/move?<overrides..>", format = "json", data = "<move_req>")]
+fn handle_team_move(
+    snakes: &State<Snakes>,
+    route: &Route,
+    team: &str,
+    overrides: WeightOverrides,
+    move_req: Json<GameState>,
+) -> Json<Value> {
+    move_game(served(snakes, route), Some(team), overrides, &move_req)
+}
+
+fn move_game(
+    snake: &Snake,
+    team: Option<&str>,
+    overrides: WeightOverrides,
+    move_req: &GameState,
+) -> Json<Value> {
+    // The time budget starts before we wait for any lock, a teammate may be
+    // searching in the same game
+    let arrived = Instant::now();
+    // Retrieve game information from shared data, a game we never saw start
+    // is rebuilt from the move itself. The registry is only locked for the
+    // lookup, the search holds the lock of this game and team alone
+    let game = snake.registry.lock().unwrap().game(move_req, team, snake.config.weights, snake.config.engine);
+    let mut game_info = game.lock().unwrap();
+    // Overrides on a move only last for that move
+    let weights = overrides.apply(game_info.weights);
     let response = logic::get_move(
         &move_req.game,
         &move_req.turn,
         &move_req.board,
         &move_req.you,
-        game_info
+        &mut game_info,
+        weights,
+        arrived,
     );
 
     Json(response)
 }
 
 #[post("/end", format = "json", data = "<end_req>")]
-fn handle_end(shared_data: &State<SharedData>, end_req: Json<GameState>) -> Status {
+fn handle_end(snakes: &State<Snakes>, route: &Route, end_req: Json<GameState>) -> Status {
+    end_game(served(snakes, route), None, &end_req)
+}
+
+#[post("/team/<team>/end", format = "json", data = "<end_req>")]
+fn handle_team_end(
+    snakes: &State<Snakes>,
+    route: &Route,
+    team: &str,
+    end_req: Json<GameState>,
+) -> Status {
+    end_game(served(snakes, route), Some(team), &end_req)
+}
+
+fn end_game(snake: &Snake, team: Option<&str>, end_req: &GameState) -> Status {
     logic::end(&end_req.game, &end_req.turn, &end_req.board, &end_req.you);
-    // Store game information in shared data
-    let mut data = shared_data.lock().unwrap();
-    // Check if the game ID already exists
-    let game_id = end_req.game.id.clone();
-    if let Some(_game_info) = data.get_mut(&game_id) {
-        // Remove the game info from shared data
-        data.remove(&game_id);
-    }
+    // Remove the game of our team from shared data
+    snake.registry.lock().unwrap().end(end_req, team);
 
     Status::Ok
 }
 
+// The server with the routes of every snake mounted at its path
+fn build(snakes: Snakes) -> Rocket<Build> {
+    let paths: Vec<String> = snakes.keys().cloned().collect();
+    let mut server = rocket::build()
+        .attach(AdHoc::on_response("Server ID Middleware", |_, res| {
+            Box::pin(async move {
+                res.set_raw_header("Server", "battlesnake/github/starter-snake-rust");
+            })
+        }))
+        .manage(snakes);
+    for path in paths {
+        server = server.mount(
+            path,
+            routes![
+                handle_index,
+                handle_start,
+                handle_move,
+                handle_end,
+                handle_team_index,
+                handle_team_start,
+                handle_team_move,
+                handle_team_end
+            ],
+        );
+    }
+    server
+}
+
 #[launch]
 fn rocket() -> _ {
     // Lots of web hosting services expect you to bind to the port specified by the `PORT`
@@ -192,21 +425,265 @@ fn rocket() -> _ {
         env::set_var("RUST_LOG", "info");
     }
 
-    env_logger::init();
+    // Tests build the server more than once
+    let _ = env_logger::try_init();
 
     info!("Starting Battlesnake Server...");
-    let initial_data: HashMap<String, GameInfo> = HashMap::new();
-    let shared_data = Arc::new(Mutex::new(initial_data));
+    let root = SnakeConfig::from_env().unwrap_or_else(|e| panic!("{}", e));
+    info!("Snake at /: {:?}", root);
+    let named = SnakeConfig::named_from_env().unwrap_or_else(|e| panic!("{}", e));
+    for (name, config) in named.iter() {
+        info!("Snake at /{}: {:?}", name, config);
+    }
 
-    rocket::build()
-        .attach(AdHoc::on_response("Server ID Middleware", |_, res| {
-            Box::pin(async move {
-                res.set_raw_header("Server", "battlesnake/github/starter-snake-rust");
-            })
-        }))
-        .manage(shared_data)
-        .mount(
-            "/",
-            routes![handle_index, handle_start, handle_move, handle_end],
+    build(logic::snakes::snakes(root, named))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build, rocket, GameMode, GameState, Ruleset, Topology};
+    use crate::logic::engine::Engine;
+    use crate::logic::snakes::{snakes, SnakeConfig, Snakes};
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_parse_ruleset() {
+        let ruleset: Ruleset = serde_json::from_str(
+            r#"{
+                "name": "royale",
+                "version": "v1.2.3",
+                "settings": {
+                    "foodSpawnChance": 25,
+                    "minimumFood": 2,
+                    "hazardDamagePerTurn": 15,
+                    "hazardMap": "",
+                    "royale": { "shrinkEveryNTurns": 5 },
+                    "squad": {
+                        "allowBodyCollisions": true,
+                        "sharedElimination": true,
+                        "sharedHealth": false,
+                        "sharedLength": true
+                    }
+                }
+            }"#,
         )
+        .unwrap();
+        assert_eq!(ruleset.mode(), GameMode::Royale);
+        assert_eq!(ruleset.version, "v1.2.3");
+        assert_eq!(ruleset.settings.food_spawn_chance, 25);
+        assert_eq!(ruleset.settings.minimum_food, 2);
+        assert_eq!(ruleset.settings.hazard_damage_per_turn, 15);
+        assert_eq!(ruleset.settings.royale.shrink_every_n_turns, 5);
+        assert!(ruleset.settings.squad.allow_body_collisions);
+        assert!(!ruleset.settings.squad.shared_health);
+    }
+
+    #[test]
+    fn test_parse_partial_ruleset() {
+        let ruleset: Ruleset =
+            serde_json::from_str(r#"{ "name": "constrictor", "settings": {} }"#).unwrap();
+        assert_eq!(ruleset.mode(), GameMode::Constrictor);
+        assert_eq!(ruleset.settings.food_spawn_chance, 15);
+        assert_eq!(ruleset.settings.minimum_food, 1);
+        assert_eq!(ruleset.settings.hazard_damage_per_turn, 14);
+
+        let ruleset: Ruleset = serde_json::from_str(r#"{ "name": "something-new" }"#).unwrap();
+        assert_eq!(ruleset.mode(), GameMode::Standard);
+        assert_eq!(ruleset.topology(), Topology::Bounded);
+
+        let ruleset: Ruleset = serde_json::from_str(r#"{ "name": "wrapped" }"#).unwrap();
+        assert_eq!(ruleset.mode(), GameMode::Wrapped);
+        assert_eq!(ruleset.topology(), Topology::Wrapped);
+    }
+
+    fn snake(id: &str, name: &str, squad: &str, body: &[(i32, i32)]) -> Value {
+        let body: Vec<Value> = body.iter().map(|&(x, y)| json!({ "x": x, "y": y })).collect();
+        json!({
+            "id": id,
+            "name": name,
+            "health": 90,
+            "head": body[0],
+            "length": body.len(),
+            "body": body,
+            "latency": "",
+            "shout": null,
+            "squad": squad,
+        })
+    }
+
+    // Our snake in the bottom left corner can only go right
+    fn move_request(ruleset: &str, snakes: Vec<Value>) -> Value {
+        json!({
+            "game": { "id": "lost-game", "ruleset": { "name": ruleset }, "timeout": 100 },
+            "turn": 12,
+            "board": {
+                "height": 7,
+                "width": 7,
+                "food": [],
+                "hazards": [],
+                "snakes": snakes,
+            },
+            "you": snakes[0],
+        })
+    }
+
+    #[test]
+    fn test_move_without_start() {
+        let client = Client::tracked(rocket()).unwrap();
+        let request = move_request(
+            "standard",
+            vec![
+                snake("us", "us", "", &[(0, 0), (0, 1), (0, 2)]),
+                snake("them", "them", "", &[(5, 5), (5, 4), (5, 3)]),
+            ],
+        );
+        let response = client
+            .post("/move")
+            .header(ContentType::JSON)
+            .body(request.to_string())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["move"], "right");
+    }
+
+    #[test]
+    fn test_both_teams_on_one_server() {
+        let client = Client::tracked(rocket()).unwrap();
+        let us = snake("us", "us", "", &[(0, 0), (0, 1), (0, 2)]);
+        let them = snake("them", "them", "", &[(5, 5), (5, 4), (5, 3)]);
+        for (team, snakes) in [("a", vec![us.clone(), them.clone()]), ("b", vec![them, us])] {
+            let response = client
+                .post(format!("/team/{}/start", team))
+                .header(ContentType::JSON)
+                .body(move_request("standard", snakes).to_string())
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let response = client
+            .post("/team/a/move")
+            .header(ContentType::JSON)
+            .body(move_request("standard", vec![snake("us", "us", "", &[(0, 0), (0, 1), (0, 2)])]).to_string())
+            .dispatch();
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["move"], "right");
+
+        let snakes = client.rocket().state::<Snakes>().unwrap();
+        let mut registry = snakes["/"].registry.lock().unwrap();
+        // Each team only knows its own snake
+        let state = serde_json::from_value(move_request("standard", vec![snake("them", "them", "", &[(5, 5)])])).unwrap();
+        assert_eq!(registry.end(&state, Some("a")).unwrap().lock().unwrap().agent_ids, vec!["us"]);
+        assert_eq!(registry.end(&state, Some("b")).unwrap().lock().unwrap().agent_ids, vec!["them"]);
+        assert!(registry.end(&state, Some("b")).is_none());
+    }
+
+    #[test]
+    fn test_named_snakes() {
+        let named = SnakeConfig::named_from_json(r##"{ "alpha": { "color": "#ff0000", "engine": "mcts" } }"##).unwrap();
+        let client = Client::tracked(build(snakes(SnakeConfig::default(), named))).unwrap();
+        let info: Value = client.get("/alpha").dispatch().into_json().unwrap();
+        assert_eq!(info["color"], "#ff0000");
+        let info: Value = client.get("/").dispatch().into_json().unwrap();
+        assert_eq!(info["color"], "#e83d84");
+
+        let request = move_request("standard", vec![snake("us", "us", "", &[(0, 0), (0, 1), (0, 2)])]);
+        let response = client
+            .post("/alpha/team/a/move")
+            .header(ContentType::JSON)
+            .body(request.to_string())
+            .dispatch();
+        let body: Value = response.into_json().unwrap();
+        assert_eq!(body["move"], "right");
+
+        // Only alpha knows the game
+        let state = serde_json::from_value(request).unwrap();
+        let snakes = client.rocket().state::<Snakes>().unwrap();
+        assert!(snakes["/"].registry.lock().unwrap().end(&state, Some("a")).is_none());
+        let game = snakes["/alpha"].registry.lock().unwrap().end(&state, Some("a")).unwrap();
+        assert_eq!(game.lock().unwrap().engine, Engine::Mcts);
+    }
+
+    // Parses a sample payload and checks that serializing it gives the same JSON back
+    fn round_trip(payload: &str) -> GameState {
+        let state: GameState = serde_json::from_str(payload).unwrap();
+        let expected: Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(serde_json::to_value(&state).unwrap(), expected);
+        state
+    }
+
+    #[test]
+    fn test_payload_standard() {
+        let state = round_trip(include_str!("../tests/payloads/standard.json"));
+        assert_eq!(state.game.ruleset.mode(), GameMode::Standard);
+        assert_eq!(state.game.map, "standard");
+        assert_eq!(state.game.source, "league");
+        assert_eq!(state.you.customizations.color, "#e83d84");
+        assert_eq!(state.you.shout.as_deref(), Some("why are we shouting??"));
+        assert_eq!(state.board.snakes[1].health, 16);
+    }
+
+    #[test]
+    fn test_payload_royale() {
+        let state = round_trip(include_str!("../tests/payloads/royale.json"));
+        assert_eq!(state.game.ruleset.mode(), GameMode::Royale);
+        assert_eq!(state.game.ruleset.settings.royale.shrink_every_n_turns, 25);
+        assert_eq!(state.board.hazards.len(), 5);
+    }
+
+    #[test]
+    fn test_payload_constrictor() {
+        let state = round_trip(include_str!("../tests/payloads/constrictor.json"));
+        assert_eq!(state.game.ruleset.mode(), GameMode::Constrictor);
+        assert_eq!(state.game.ruleset.settings.hazard_damage_per_turn, 0);
+        assert!(state.board.food.is_empty());
+    }
+
+    #[test]
+    fn test_payload_wrapped() {
+        let state = round_trip(include_str!("../tests/payloads/wrapped.json"));
+        assert_eq!(state.game.ruleset.topology(), Topology::Wrapped);
+        assert_eq!(state.game.map, "arcade_maze");
+        assert_eq!(state.game.ruleset.settings.hazard_map_author, "altersaddle");
+        assert_eq!((state.board.width, state.board.height), (19, 21));
+    }
+
+    #[test]
+    fn test_payload_solo() {
+        let state = round_trip(include_str!("../tests/payloads/solo.json"));
+        assert_eq!(state.game.ruleset.mode(), GameMode::Solo);
+        assert_eq!(state.board.snakes.len(), 1);
+        assert_eq!(state.you.latency, "");
+    }
+
+    #[test]
+    fn test_payload_squad() {
+        let state = round_trip(include_str!("../tests/payloads/squad.json"));
+        assert_eq!(state.game.ruleset.mode(), GameMode::Squad);
+        assert!(state.game.ruleset.settings.squad.shared_elimination);
+        assert!(!state.game.ruleset.settings.squad.shared_length);
+        let squads: Vec<&str> = state.board.snakes.iter().map(|s| s.squad.as_str()).collect();
+        assert_eq!(squads, vec!["1", "1", "2", "2"]);
+    }
+
+    #[test]
+    fn test_payload_optional_fields() {
+        // Older engines and hand written requests leave out the optional fields
+        let state: GameState = serde_json::from_value(move_request("standard", vec![json!({
+            "id": "us",
+            "name": "us",
+            "health": 100,
+            "body": [{ "x": 0, "y": 0 }],
+            "head": { "x": 0, "y": 0 },
+            "length": 1,
+        })]))
+        .unwrap();
+        assert_eq!(state.game.map, "");
+        assert_eq!(state.game.source, "");
+        assert_eq!(state.you.latency, "");
+        assert_eq!(state.you.shout, None);
+        assert_eq!(state.you.squad, "");
+        assert_eq!(state.you.customizations.color, "");
+    }
 }